@@ -1,6 +1,22 @@
 pub mod upload;
 
-use upload::commands::{download_file, gen_full_res, generate_thumbnails};
+use tauri::{Manager, RunEvent};
+use tauri_plugin_dialog::{DialogExt, MessageDialogKind};
+use upload::cache::ThumbnailCache;
+use upload::commands::{
+    cancel_job, change_security, close_document, delete_recipe, download_file, gen_full_res,
+    generate_thumbnails, get_job_progress, get_render_settings, list_recipes, open_project,
+    preflight_export, render_region, run_recipe, save_project, save_recipe, set_render_concurrency,
+    set_render_settings, unwatch_recipe, watch_recipe,
+};
+use upload::jobs::JobManager;
+use upload::pdfium::PdfiumState;
+use upload::pool::RenderPool;
+use upload::protocol::{handle_page_request, DocumentRegistry, PROTOCOL_NAME};
+use upload::recipe::RecipeStore;
+use upload::settings::RenderSettings;
+use upload::watch::RecipeWatcher;
+use upload::workspace::TempWorkspace;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -8,11 +24,74 @@ pub fn run() {
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
+        .setup(|app| {
+            app.manage(ThumbnailCache::new(app.handle())?);
+            app.manage(JobManager::default());
+            app.manage(RenderPool::new()?);
+            app.manage(DocumentRegistry::default());
+            app.manage(RenderSettings::default());
+            app.manage(RecipeStore::new(app.handle())?);
+            app.manage(RecipeWatcher::default());
+            app.manage(TempWorkspace::new(app.handle())?);
+
+            // Without pdfium nothing can be previewed, so tell the user right away instead of
+            // failing on the first file they open
+            match PdfiumState::new(app.handle()) {
+                Ok(pdfium) => {
+                    app.manage(pdfium);
+                }
+                Err(e) => {
+                    let handle = app.handle().clone();
+                    app.dialog()
+                        .message(format!(
+                            "The PDF rendering library could not be loaded. Please reinstall Ignis.\n\n{}",
+                            e
+                        ))
+                        .title("Ignis")
+                        .kind(MessageDialogKind::Error)
+                        .show(move |_| handle.exit(1));
+                }
+            }
+            Ok(())
+        })
+        .register_asynchronous_uri_scheme_protocol(PROTOCOL_NAME, |ctx, request, responder| {
+            // Render off the webview thread, sharing the thumbnail encoder pool
+            let app = ctx.app_handle().clone();
+            let encoders = app.state::<RenderPool>().encoders();
+            encoders.spawn(move || {
+                responder.respond(handle_page_request(&app, &request));
+            });
+        })
         .invoke_handler(tauri::generate_handler![
             generate_thumbnails,
             gen_full_res,
+            render_region,
+            preflight_export,
             download_file,
+            change_security,
+            cancel_job,
+            close_document,
+            get_job_progress,
+            set_render_concurrency,
+            get_render_settings,
+            set_render_settings,
+            save_project,
+            open_project,
+            list_recipes,
+            save_recipe,
+            delete_recipe,
+            run_recipe,
+            watch_recipe,
+            unwatch_recipe,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            // Converted and repaired files are only needed while the app runs
+            if let RunEvent::Exit = event {
+                if let Some(workspace) = app.try_state::<TempWorkspace>() {
+                    workspace.purge();
+                }
+            }
+        });
 }
@@ -0,0 +1,241 @@
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use pdfium_render::prelude::*;
+use std::collections::BTreeMap;
+
+use crate::upload::enums::PageBox;
+use crate::upload::error::ErrorCode;
+use crate::upload::functions::clone_object;
+use crate::upload::structs::RegionRect;
+
+// [left, bottom, right, top] in default user space
+pub(crate) type BoxRect = [f32; 4];
+
+// US Letter, what readers assume for pages without a MediaBox
+const DEFAULT_MEDIA_BOX: BoxRect = [0.0, 0.0, 612.0, 792.0];
+
+// Page attributes that may be set on an ancestor in the page tree instead of the page itself
+const INHERITABLE_KEYS: [&[u8]; 4] = [b"MediaBox", b"CropBox", b"Rotate", b"Resources"];
+
+/// Size of the page in points as readers display it: the CropBox clipped to the MediaBox,
+/// with width and height swapped for pages rotated by 90 or 270 degrees.
+pub(crate) fn page_display_size(page: &PdfPage) -> (f32, f32) {
+    let boundaries = page.boundaries();
+    let Ok(media) = boundaries.media() else {
+        return (page.width().value, page.height().value);
+    };
+
+    let media = pdf_rect_to_box(&media.bounds);
+    let visible = boundaries
+        .crop()
+        .ok()
+        .and_then(|crop| intersect(pdf_rect_to_box(&crop.bounds), media))
+        .unwrap_or(media);
+
+    let (width, height) = (visible[2] - visible[0], visible[3] - visible[1]);
+    match page.rotation() {
+        Ok(PdfPageRenderRotation::Degrees90) | Ok(PdfPageRenderRotation::Degrees270) => {
+            (height, width)
+        }
+        _ => (width, height),
+    }
+}
+
+/// Like [`page_display_size`], for a page in a document being written.
+pub(crate) fn page_display_size_of(doc: &Document, page_id: ObjectId) -> (f32, f32) {
+    let [left, bottom, right, top] = crop_box(doc, page_id);
+    match page_rotation(doc, page_id) {
+        90 | 270 => (top - bottom, right - left),
+        _ => (right - left, top - bottom),
+    }
+}
+
+fn pdf_rect_to_box(rect: &PdfRect) -> BoxRect {
+    normalize([
+        rect.left().value,
+        rect.bottom().value,
+        rect.right().value,
+        rect.top().value,
+    ])
+}
+
+fn normalize(rect: BoxRect) -> BoxRect {
+    [
+        rect[0].min(rect[2]),
+        rect[1].min(rect[3]),
+        rect[0].max(rect[2]),
+        rect[1].max(rect[3]),
+    ]
+}
+
+fn intersect(a: BoxRect, b: BoxRect) -> Option<BoxRect> {
+    let rect = [a[0].max(b[0]), a[1].max(b[1]), a[2].min(b[2]), a[3].min(b[3])];
+    (rect[2] > rect[0] && rect[3] > rect[1]).then_some(rect)
+}
+
+/// Looks the attribute up on the page and then on its ancestors, resolving references.
+fn inherited_attribute(doc: &Document, page_id: ObjectId, key: &[u8]) -> Option<Object> {
+    let mut dict = doc.get_dictionary(page_id).ok()?;
+
+    // Bounded, in case of a cycle in a broken page tree
+    for _ in 0..32 {
+        if let Ok(value) = dict.get(key) {
+            return doc.dereference(value).ok().map(|(_, value)| value.clone());
+        }
+        let parent = dict.get(b"Parent").and_then(Object::as_reference).ok()?;
+        dict = doc.get_dictionary(parent).ok()?;
+    }
+
+    None
+}
+
+fn read_box(doc: &Document, page_id: ObjectId, key: &[u8]) -> Option<BoxRect> {
+    let key_is_inheritable = INHERITABLE_KEYS.contains(&key);
+    let value = if key_is_inheritable {
+        inherited_attribute(doc, page_id, key)?
+    } else {
+        let value = doc.get_dictionary(page_id).ok()?.get(key).ok()?;
+        doc.dereference(value).ok()?.1.clone()
+    };
+
+    let values = value
+        .as_array()
+        .ok()?
+        .iter()
+        .map(|v| doc.dereference(v).ok().and_then(|(_, v)| v.as_float().ok()))
+        .collect::<Option<Vec<f32>>>()?;
+
+    match values.as_slice() {
+        [left, bottom, right, top] => Some(normalize([*left, *bottom, *right, *top])),
+        _ => None,
+    }
+}
+
+pub(crate) fn page_rotation(doc: &Document, page_id: ObjectId) -> i64 {
+    let rotation = inherited_attribute(doc, page_id, b"Rotate")
+        .and_then(|rotate| rotate.as_i64().ok())
+        .unwrap_or(0);
+
+    // Only multiples of 90 are valid; anything else is rounded down like readers do
+    (rotation.rem_euclid(360) / 90) * 90
+}
+
+fn media_box(doc: &Document, page_id: ObjectId) -> BoxRect {
+    read_box(doc, page_id, b"MediaBox").unwrap_or(DEFAULT_MEDIA_BOX)
+}
+
+/// The visible area of the page: the CropBox clipped to the MediaBox.
+pub(crate) fn crop_box(doc: &Document, page_id: ObjectId) -> BoxRect {
+    let media = media_box(doc, page_id);
+    read_box(doc, page_id, b"CropBox")
+        .and_then(|crop| intersect(crop, media))
+        .unwrap_or(media)
+}
+
+/// Bleed, trim and art boxes default to the CropBox when they are missing.
+pub(crate) fn page_box(doc: &Document, page_id: ObjectId, page_box: PageBox) -> BoxRect {
+    let media = media_box(doc, page_id);
+    let crop = crop_box(doc, page_id);
+    let key: &[u8] = match page_box {
+        PageBox::Media => return media,
+        PageBox::Crop => return crop,
+        PageBox::Bleed => b"BleedBox",
+        PageBox::Trim => b"TrimBox",
+        PageBox::Art => b"ArtBox",
+    };
+
+    read_box(doc, page_id, key)
+        .and_then(|rect| intersect(rect, media))
+        .unwrap_or(crop)
+}
+
+/// Maps a rectangle given on the page as displayed (in points from its top left corner, after
+/// cropping and rotation) back into default user space.
+pub(crate) fn displayed_rect_to_user_space(
+    rect: RegionRect,
+    crop: BoxRect,
+    rotation: i64,
+) -> BoxRect {
+    let [left, bottom, right, top] = crop;
+    let to_user_space = |u: f32, v: f32| -> (f32, f32) {
+        match rotation {
+            90 => (left + v, bottom + u),
+            180 => (right - u, bottom + v),
+            270 => (right - v, top - u),
+            _ => (left + u, top - v),
+        }
+    };
+
+    let (x0, y0) = to_user_space(rect.x, rect.y);
+    let (x1, y1) = to_user_space(rect.x + rect.width, rect.y + rect.height);
+    normalize([x0, y0, x1, y1])
+}
+
+/// The CropBox an exported page should get, or `None` to keep the one it has.
+/// A rectangle drawn by the user wins over a page box chosen for the whole export.
+pub(crate) fn export_crop_box(
+    doc: &Document,
+    page_id: ObjectId,
+    crop: Option<RegionRect>,
+    trim_to: Option<PageBox>,
+) -> Option<BoxRect> {
+    let visible = crop_box(doc, page_id);
+
+    if let Some(crop) = crop {
+        let rect = displayed_rect_to_user_space(crop, visible, page_rotation(doc, page_id));
+        return intersect(rect, visible);
+    }
+
+    trim_to.map(|trim_to| page_box(doc, page_id, trim_to))
+}
+
+pub(crate) fn box_object(rect: BoxRect) -> Object {
+    Object::Array(rect.iter().map(|&v| Object::Real(v)).collect())
+}
+
+/// Copies a page into `new_doc` under `parent_id`. Attributes the page inherits from its old page
+/// tree are written onto the copy, since that tree is not copied along.
+pub(crate) fn import_page(
+    doc: &Document,
+    page_id: ObjectId,
+    new_doc: &mut Document,
+    id_map: &mut BTreeMap<ObjectId, ObjectId>,
+    parent_id: ObjectId,
+    crop_box: Option<BoxRect>,
+) -> Result<ObjectId, ErrorCode> {
+    let page = doc
+        .get_dictionary(page_id)
+        .map_err(|_| ErrorCode::PdfPageNotFound)?;
+
+    // A page exported twice becomes two pages; references to it (e.g. from its annotations)
+    // point at the first copy
+    let new_id = new_doc.new_object_id();
+    id_map.entry(page_id).or_insert(new_id);
+
+    let mut new_page = Dictionary::new();
+    for (key, value) in page.iter() {
+        if key.as_slice() == b"Parent" {
+            continue;
+        }
+        new_page.set(key.clone(), clone_object(value, doc, new_doc, id_map));
+    }
+
+    for key in INHERITABLE_KEYS {
+        if !new_page.has(key) {
+            if let Some(value) = inherited_attribute(doc, page_id, key) {
+                new_page.set(key, clone_object(&value, doc, new_doc, id_map));
+            }
+        }
+    }
+    // Readers need a MediaBox; the default is what they would have assumed anyway
+    if !new_page.has(b"MediaBox") {
+        new_page.set("MediaBox", box_object(DEFAULT_MEDIA_BOX));
+    }
+    if let Some(crop_box) = crop_box {
+        new_page.set("CropBox", box_object(crop_box));
+    }
+
+    new_page.set("Parent", Object::Reference(parent_id));
+    new_doc.objects.insert(new_id, Object::Dictionary(new_page));
+
+    Ok(new_id)
+}
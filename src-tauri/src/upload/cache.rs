@@ -0,0 +1,126 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tauri::{AppHandle, Manager};
+
+use crate::upload::error::{CommandError, ErrorCode};
+use crate::upload::structs::ImageEncoding;
+
+// 256 MB of encoded thumbnails is roughly 20,000 pages at the default height
+const MAX_CACHE_BYTES: u64 = 256 * 1024 * 1024;
+
+pub(crate) struct ThumbnailCacheKey<'a> {
+    pub file_hash: &'a str,
+    pub page_index: usize,
+    pub height: u32,
+    pub encoding: ImageEncoding,
+}
+
+impl ThumbnailCacheKey<'_> {
+    fn file_name(&self) -> String {
+        // Lossy entries at different qualities must not be mistaken for each other
+        let variant = if self.encoding.format.is_lossy() {
+            format!("q{}", self.encoding.quality)
+        } else {
+            "lossless".to_string()
+        };
+
+        format!(
+            "{}_{}_{}_{}.{}",
+            self.file_hash,
+            self.page_index,
+            self.height,
+            variant,
+            self.encoding.format.extension()
+        )
+    }
+}
+
+/// Encoded thumbnails on disk, keyed by source content hash, page, render size and encoding.
+/// Entries are evicted least-recently-used first, using the file modification time as the access time.
+pub struct ThumbnailCache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+impl ThumbnailCache {
+    pub(crate) fn new(app: &AppHandle) -> Result<Self, String> {
+        let dir = app
+            .path()
+            .app_cache_dir()
+            .map_err(|e| format!("Could not get cache directory: {}", e))?
+            .join("thumbnails");
+
+        fs::create_dir_all(&dir).map_err(|e| format!("Could not create cache directory: {}", e))?;
+
+        Ok(Self {
+            dir,
+            max_bytes: MAX_CACHE_BYTES,
+        })
+    }
+
+    /// Returns the cached image bytes along with their pixel dimensions.
+    pub(crate) fn get(&self, key: &ThumbnailCacheKey) -> Option<(Vec<u8>, u32, u32)> {
+        let path = self.dir.join(key.file_name());
+        let buffer = fs::read(&path).ok()?;
+
+        let (width, height) = image::ImageReader::new(std::io::Cursor::new(&buffer))
+            .with_guessed_format()
+            .ok()?
+            .into_dimensions()
+            .ok()?;
+
+        // Mark the entry as recently used
+        if let Ok(file) = fs::File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+
+        Some((buffer, width, height))
+    }
+
+    pub(crate) fn put(&self, key: &ThumbnailCacheKey, buffer: &[u8]) {
+        // A failed write only costs a re-render next time
+        let _ = fs::write(self.dir.join(key.file_name()), buffer);
+    }
+
+    /// Removes the least recently used entries until the cache fits within its size cap.
+    pub(crate) fn enforce_size_limit(&self) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+
+        let mut files: Vec<(PathBuf, u64, SystemTime)> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                if !metadata.is_file() {
+                    return None;
+                }
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                Some((entry.path(), metadata.len(), modified))
+            })
+            .collect();
+
+        let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+        if total <= self.max_bytes {
+            return;
+        }
+
+        files.sort_by_key(|(_, _, modified)| *modified);
+        for (path, size, _) in files {
+            if total <= self.max_bytes {
+                break;
+            }
+            if fs::remove_file(&path).is_ok() {
+                total -= size;
+            }
+        }
+    }
+}
+
+pub(crate) fn hash_file(path: &Path) -> Result<String, CommandError> {
+    let content = fs::read(path).map_err(|e| ErrorCode::PdfLoadError.because(e).with_file(path))?;
+    Ok(format!("{:x}", md5::compute(content)))
+}
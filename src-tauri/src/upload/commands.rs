@@ -1,39 +1,125 @@
-use lopdf::{Dictionary, Document, EncryptionState, EncryptionVersion, Object, ObjectId};
-use pdfium_render::prelude::*;
-use std::collections::BTreeMap;
-use std::{fs, io::Cursor, path::Path};
-use tauri::{AppHandle};
+use lopdf::{Bookmark, Dictionary, Document, Object, ObjectId};
+use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, PoisonError};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tauri::{AppHandle, Emitter, Manager, State};
 use uuid::Uuid;
 
-use crate::upload::enums::ImageQuality;
-use crate::upload::functions::{file_name_from_path, gen_data_url_from_buffer};
-use crate::upload::structs::{CommandErrRes, DownloadSuccessRes, EncryptionLevel};
+use crate::upload::boxes::{
+    export_crop_box, import_page, page_display_size, page_display_size_of,
+};
+use crate::upload::cache::ThumbnailCache;
+use crate::upload::enums::{ExportEntry, GeneratedPage, ImageQuality, OverwritePolicy, PaperSize};
+use crate::upload::error::{CommandError, ErrorCode};
+use crate::upload::jobs::{JobHandle, JobManager};
+use crate::upload::layout::{add_blank_page, fit_page_to_size, impose_pages};
+use crate::upload::output::PendingOutput;
+use crate::upload::preflight::preflight_entries;
+use crate::upload::project::{check_sources, project_path, read_project, write_project};
+use crate::upload::repair::repair_file;
+use crate::upload::recipe::{recipe_entries, remove_temp_files, resolve_recipe, RecipeStore};
+use crate::upload::sync::lock;
+use crate::upload::toc::{add_toc_pages, TocEntry};
+use crate::upload::watch::RecipeWatcher;
+use crate::upload::workspace::TempWorkspace;
+use crate::upload::pdfium::{pdfium_state, PdfiumState};
+use crate::upload::pool::RenderPool;
+use crate::upload::settings::RenderSettings;
+use crate::upload::protocol::{page_url, DocumentRegistry, RegisteredDocument};
+use crate::upload::functions::{
+    check_export_permissions, encrypt_document, ensure_document_id, file_name_from_path,
+    encode_image, fit_region, gen_render_size, region_pixel_size, get_document_permissions,
+    load_document, load_source_document, missing_object_count,
+    open_error, permissions_from_data, random_owner_password, render_separator_page,
+    render_thumbnail_image, thumbnail_cache_key,
+    SourceDocument, THUMBNAIL_HEIGHT,
+};
+use crate::upload::structs::{
+    BrokenReferences, DownloadSuccessRes, EncryptionLevel, ExportOptions, OutputDestination,
+    PermissionsData, MergeRecipe, PreflightReport, ProjectData, ProjectOpenRes, RecipeListRes,
+    RegionImageData, RegionRect, RenderSettingsData,
+};
 use crate::upload::{
     functions::{
-        clone_with_dependencies, convert_image_to_pdf, convert_text_to_pdf, gen_image_bitmap,
-        gen_image_data_url, get_file_ext, get_output_path, get_temp_pdf_path, init_pdfium,
+        convert_image_to_pdf, convert_text_to_pdf, get_file_ext, get_output_path,
+    },
+    structs::{
+        DocumentData, FullImageData, JobProgress, ThumbnailData, ThumbnailDownloadData,
+        ThumbnailReadyEvent, ThumbnailsFinishedEvent,
     },
-    structs::{DocumentData, FullImageData, ThumbnailData, ThumbnailDownloadData},
 };
 use lopdf::encryption::{Permissions};
-use std::convert::TryFrom;
-use std::sync::Arc;
 
+/// Checks the pages of an export before `download_file` runs, see `preflight_entries`.
 #[tauri::command]
+pub async fn preflight_export(
+    app: AppHandle,
+    thumbnails: Vec<ExportEntry>,
+) -> Result<PreflightReport, CommandError> {
+    preflight_entries(&app, &thumbnails)
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn download_file(
     app: AppHandle,
+    jobs: State<'_, JobManager>,
     file_name: &str,
-    thumbnails: Vec<ThumbnailDownloadData>,
+    thumbnails: Vec<ExportEntry>,
     password: Option<String>,
     encryption_level: Option<EncryptionLevel>,
-) -> Result<DownloadSuccessRes, CommandErrRes> {
+    job_id: Option<String>,
+    options: Option<ExportOptions>,
+    destination: Option<OutputDestination>,
+) -> Result<DownloadSuccessRes, CommandError> {
+    let job = jobs.start(job_id);
+    let options = options.unwrap_or_default();
+    let destination = destination.unwrap_or_default();
+    let result = merge_pages(
+        &app,
+        &job,
+        file_name,
+        &thumbnails,
+        password,
+        encryption_level,
+        &options,
+        &destination,
+    );
+    jobs.finish(&job);
+    result
+}
 
-    let output_path = get_output_path(&app, file_name).map_err(|_| CommandErrRes {
-        key: "PDF_OUTPUT_PATH_ERROR".to_string(),
-        file_name: file_name.to_string(),
-        file_path: None,
-        page_index: None,
-    })?;
+#[allow(clippy::too_many_arguments)]
+fn merge_pages(
+    app: &AppHandle,
+    job: &JobHandle,
+    file_name: &str,
+    entries: &[ExportEntry],
+    password: Option<String>,
+    encryption_level: Option<EncryptionLevel>,
+    options: &ExportOptions,
+    destination: &OutputDestination,
+) -> Result<DownloadSuccessRes, CommandError> {
+    let thumbnails: Vec<&ThumbnailDownloadData> = entries
+        .iter()
+        .filter_map(|entry| match entry {
+            ExportEntry::Page(thumb) => Some(thumb),
+            ExportEntry::Generated(_) => None,
+        })
+        .collect();
+
+    // Errors name the export, and the source file and page they are about if there is one
+    let fail = |error: CommandError| error.with_file_name(file_name);
+    let fail_on = |thumb: &ThumbnailDownloadData, error: CommandError| {
+        fail(error).with_file(&thumb.file_path).with_page(thumb.page_index)
+    };
+    let cancelled = || fail(CommandError::new(ErrorCode::PdfDownloadCancelled));
+
+    let output_path = get_output_path(app, file_name, destination).map_err(fail)?;
 
     // Determine PDF version based on password and encryption_level
     let pdf_version = if password.is_none() {
@@ -47,54 +133,154 @@ pub async fn download_file(
     };
 
     let mut new_doc = Document::with_version(pdf_version);
+    let pages_id = new_doc.new_object_id();
     let mut new_pages = Vec::new();
     let mut global_id_map: BTreeMap<String, BTreeMap<ObjectId, ObjectId>> = BTreeMap::new();
 
+    // Refuse to copy pages out of files whose permissions forbid it
+    let pdfium = pdfium_state(app).map_err(fail)?;
+    let mut checked_files = std::collections::HashSet::new();
     for thumb in &thumbnails {
-        let mut doc = lopdf::Document::load(&thumb.file_path)
-            .map_err(|_| CommandErrRes {
-                key: "PDF_PAGE_LOAD_ERROR".to_string(),
-                file_name: file_name.to_string(),
-                file_path: Some(thumb.file_path.clone()),
-                page_index: Some(thumb.page_index),
-            })?;
-
-        // Decrypt if password is provided
-        if let Some(ref password) = thumb.password {
-            if doc.is_encrypted() {
-                doc.decrypt(password)
-                    .map_err(|_| CommandErrRes {
-                        key: "PDF_PAGE_DECRYPT_ERROR".to_string(),
-                        file_name: file_name.to_string(),
-                        file_path: Some(thumb.file_path.clone()),
-                        page_index: Some(thumb.page_index),
-                    })?;
-                doc.trailer.remove(b"Encrypt");
-            }
+        if !checked_files.insert(thumb.file_path.as_str()) {
+            continue;
+        }
+        check_export_permissions(&pdfium, &thumb.file_path, thumb.password.as_deref())
+            .map_err(|e| fail_on(thumb, e))?;
+    }
+
+    // Parse and decrypt every source exactly once, independent sources in parallel
+    let mut sources: Vec<&ThumbnailDownloadData> = Vec::new();
+    for &thumb in &thumbnails {
+        let same_source = |source: &&ThumbnailDownloadData| {
+            source.file_path == thumb.file_path && source.password == thumb.password
+        };
+        if !sources.iter().any(same_source) {
+            sources.push(thumb);
+        }
+    }
+
+    let loaded_docs: HashMap<(&str, Option<&str>), SourceDocument> = sources
+        .par_iter()
+        .map(|&thumb| {
+            let key = (thumb.file_path.as_str(), thumb.password.as_deref());
+            load_source_document(key.0, key.1)
+                .map(|source| (key, source))
+                .map_err(|e| fail_on(thumb, e))
+        })
+        .collect::<Result<_, _>>()?;
+
+    job.set_total(entries.len());
+
+    // The source file of the pages added last and how many there were, to pad it to even
+    let mut current_run: Option<(&str, usize)> = None;
+    let mut toc_entries: Vec<TocEntry> = Vec::new();
+
+    for entry in entries {
+        if job.is_cancelled() {
+            return Err(cancelled());
         }
 
+        let thumb = match entry {
+            ExportEntry::Page(thumb) => thumb,
+            ExportEntry::Generated(page) => {
+                let finished_run = current_run.take();
+                if options.pad_to_even {
+                    pad_to_even(&mut new_doc, pages_id, &mut new_pages, finished_run);
+                }
+                let previous_page = new_pages.last().copied();
+                let ids = add_generated_page(app, &mut new_doc, pages_id, page, previous_page)
+                    .map_err(fail)?;
+                for new_id in ids {
+                    fit_to_paper(&mut new_doc, new_id, options)
+                        .map_err(|code| fail(CommandError::new(code)))?;
+                    new_pages.push(new_id);
+                }
+
+                let _ = app.emit("job-progress", job.advance());
+                continue;
+            }
+        };
+
+        let starts_document = match &mut current_run {
+            Some((path, count)) if *path == thumb.file_path => {
+                *count += 1;
+                false
+            }
+            _ => {
+                if options.pad_to_even {
+                    pad_to_even(&mut new_doc, pages_id, &mut new_pages, current_run);
+                }
+                current_run = Some((thumb.file_path.as_str(), 1));
+                true
+            }
+        };
+
+        let source = &loaded_docs[&(thumb.file_path.as_str(), thumb.password.as_deref())];
+        let doc = &source.doc;
+
         // Get the page object ID for the given page_index (lopdf is 1-based)
-        let pages = doc.get_pages();
-        let page_id = match pages.get(&((thumb.page_index + 1) as u32)) {
+        let page_id = match source.pages.get(&((thumb.page_index + 1) as u32)) {
             Some(id) => *id,
-            None => {
-                return Err(CommandErrRes {
-                    key: "PDF_PAGE_NOT_FOUND".to_string(),
-                    file_name: file_name.to_string(),
-                    file_path: Some(thumb.file_path.clone()),
-                    page_index: Some(thumb.page_index),
-                })
-            }
+            None => return Err(fail_on(thumb, CommandError::new(ErrorCode::PdfPageNotFound))),
         };
 
         // Merge the page into the new document
+        let crop_box = export_crop_box(doc, page_id, thumb.crop, options.trim_to);
         let id_map = global_id_map.entry(thumb.file_path.clone()).or_default();
-        let new_id = clone_with_dependencies(&doc, page_id, &mut new_doc, id_map);
+        let new_id = import_page(doc, page_id, &mut new_doc, id_map, pages_id, crop_box)
+            .map_err(|code| fail_on(thumb, CommandError::new(code)))?;
+
+        fit_to_paper(&mut new_doc, new_id, options)
+            .map_err(|code| fail_on(thumb, CommandError::new(code)))?;
+        if starts_document {
+            toc_entries.push(TocEntry {
+                title: file_name_from_path(&thumb.file_path),
+                page_index: new_pages.len(),
+                target: new_id,
+            });
+        }
         new_pages.push(new_id);
+
+        let _ = app.emit("job-progress", job.advance());
+    }
+
+    if options.pad_to_even {
+        pad_to_even(&mut new_doc, pages_id, &mut new_pages, current_run);
+    }
+
+    // Without any source document, e.g. only generated pages, there is nothing to list
+    let toc = options.table_of_contents.as_ref().filter(|_| !toc_entries.is_empty());
+    if let (Some(toc), Some(&first_page)) = (toc, new_pages.first()) {
+        let size = page_display_size_of(&new_doc, first_page);
+        let toc_pages = add_toc_pages(
+            app,
+            &mut new_doc,
+            pages_id,
+            &toc.title,
+            &toc_entries,
+            new_pages.len(),
+            options.layout,
+            size,
+        )
+        .map_err(|code| fail(CommandError::new(code)))?;
+        new_pages.splice(0..0, toc_pages);
+    }
+
+    let mut sheet_of = BTreeMap::new();
+    if let Some(layout) = options.layout {
+        let paper = options.page_size.map(|page_size| page_size.dimensions());
+        (new_pages, sheet_of) = impose_pages(
+            &mut new_doc,
+            &new_pages,
+            pages_id,
+            layout,
+            paper,
+            options.fixed_orientation,
+        )
+        .map_err(|code| fail(CommandError::new(code)))?;
     }
 
     // Build the Pages tree
-    let pages_id = new_doc.new_object_id();
     let kids: Vec<Object> = new_pages.iter().map(|&id| id.into()).collect();
     let mut pages_dict = Dictionary::new();
     pages_dict.set("Type", Object::Name(b"Pages".to_vec()));
@@ -109,385 +295,803 @@ pub async fn download_file(
     let mut catalog_dict = Dictionary::new();
     catalog_dict.set("Type", Object::Name(b"Catalog".to_vec()));
     catalog_dict.set("Pages", Object::Reference(pages_id));
+
+    if options.table_of_contents.as_ref().is_some_and(|toc| toc.outlines) {
+        for entry in toc_entries {
+            // With a layout, the bookmark leads to the sheet the page was placed on
+            let target = sheet_of.get(&entry.target).copied().unwrap_or(entry.target);
+            new_doc.add_bookmark(Bookmark::new(entry.title, [0.0; 3], 0, target), None);
+        }
+        if let Some(outline_id) = new_doc.build_outline() {
+            catalog_dict.set("Outlines", Object::Reference(outline_id));
+            catalog_dict.set("PageMode", Object::Name(b"UseOutlines".to_vec()));
+        }
+    }
+
     new_doc
         .objects
         .insert(catalog_id, Object::Dictionary(catalog_dict));
     new_doc.trailer.set("Root", catalog_id);
 
-    // Ensure /ID is present in the trailer
-    if new_doc.trailer.get(b"ID").is_err() {
-        // Generate a random 16-byte ID (twice, as per PDF spec)
-        let id = Uuid::new_v4().as_bytes().to_vec();
-        let id_obj = Object::Array(vec![
-            Object::String(id.clone(), lopdf::StringFormat::Hexadecimal),
-            Object::String(id, lopdf::StringFormat::Hexadecimal),
-        ]);
-        new_doc.trailer.set("ID", id_obj);
+    // Content streams and pages replaced while laying out pages are no longer referenced
+    if options.page_size.is_some() || options.layout.is_some() {
+        new_doc.prune_objects();
     }
 
+    ensure_document_id(&mut new_doc);
+
     if let Some(password) = password {
         if !new_pages.is_empty() {
-            let owner_password = &password;
-            let user_password = &password;
-            let permissions = Permissions::default();
-
             let enc_level = encryption_level.unwrap_or(EncryptionLevel::Aes128);
 
-            let mut crypt_filters: BTreeMap<Vec<u8>, Arc<dyn lopdf::encryption::crypt_filters::CryptFilter>> = BTreeMap::new();
-            let stream_filter = b"Default".to_vec();
-            let string_filter = b"Default".to_vec();
-
-            if enc_level == EncryptionLevel::Aes128 {
-                // Use V4 encryption with AES-128 for PDF version 1.5
-                crypt_filters.insert(b"Default".to_vec(), Arc::new(lopdf::encryption::crypt_filters::Aes128CryptFilter));
-                let version = EncryptionVersion::V4 {
-                    document: &new_doc,
-                    encrypt_metadata: true,
-                    crypt_filters,
-                    stream_filter,
-                    string_filter,
-                    owner_password,
-                    user_password,
-                    permissions,
-                };
-
-                let state = EncryptionState::try_from(version)
-                    .map_err(|e| {
-                        println!("EncryptionState::try_from error (AES-128): {e:?}");
-                        CommandErrRes {
-                            key: "PDF_ENCRYPT_ERROR".to_string(),
-                            file_name: file_name.to_string(),
-                            file_path: Some(output_path.to_string_lossy().to_string()),
-                            page_index: None,
-                        }
-                    })?;
-
-                new_doc.encrypt(&state)
-                    .map_err(|e| {
-                        println!("new_doc.encrypt error (AES-128): {e:?}");
-                        CommandErrRes {
-                            key: "PDF_ENCRYPT_ERROR".to_string(),
-                            file_name: file_name.to_string(),
-                            file_path: Some(output_path.to_string_lossy().to_string()),
-                            page_index: None,
-                        }
-                    })?;
-            } else {
-                // For AES-256, generate a 32-byte key and use V5 encryption (which requires PDF version 1.7)
-                use lopdf::encryption::crypt_filters::Aes256CryptFilter;
-                use rand::Rng;
-
-                let mut file_encryption_key = [0u8; 32];
-                rand::thread_rng().fill(&mut file_encryption_key);
-
-                crypt_filters.insert(b"Default".to_vec(), Arc::new(Aes256CryptFilter));
-
-                let version = EncryptionVersion::V5 {
-                    encrypt_metadata: true,
-                    crypt_filters,
-                    file_encryption_key: &file_encryption_key,
-                    stream_filter,
-                    string_filter,
-                    owner_password,
-                    user_password,
-                    permissions,
-                };
-
-                let state = EncryptionState::try_from(version)
-                    .map_err(|e| {
-                        println!("EncryptionState::try_from error (AES-256): {e:?}");
-                        CommandErrRes {
-                            key: "PDF_ENCRYPT_ERROR".to_string(),
-                            file_name: file_name.to_string(),
-                            file_path: Some(output_path.to_string_lossy().to_string()),
-                            page_index: None,
-                        }
-                    })?;
-
-                new_doc.encrypt(&state)
-                    .map_err(|e| {
-                        println!("new_doc.encrypt error (AES-256): {e:?}");
-                        CommandErrRes {
-                            key: "PDF_ENCRYPT_ERROR".to_string(),
-                            file_name: file_name.to_string(),
-                            file_path: Some(output_path.to_string_lossy().to_string()),
-                            page_index: None,
-                        }
-                    })?;
-            }
+            encrypt_document(&mut new_doc, &password, &password, enc_level, Permissions::default())
+                .map_err(|e| fail(e).with_file(&output_path))?;
         }
     }
 
-    // Save the new PDF
-    new_doc
-        .save(&output_path)
-        .map_err(|_| CommandErrRes {
-            key: "PDF_SAVE_ERROR".to_string(),
-            file_name: file_name.to_string(),
-            file_path: Some(output_path.to_string_lossy().to_string()),
-            page_index: None,
+    if job.is_cancelled() {
+        return Err(cancelled());
+    }
+
+    // Save the new PDF under a temporary name, it only takes the real one once it is complete
+    let output = PendingOutput::write(&mut new_doc, &output_path).map_err(fail)?;
+
+    // Cancelled while saving: dropping the output removes it
+    if job.is_cancelled() {
+        return Err(cancelled());
+    }
+    output.commit().map_err(fail)?;
+
+    // Missing objects were exported as null, which the user is warned about
+    let broken_references = global_id_map
+        .iter()
+        .filter_map(|(file_path, id_map)| {
+            let (_, source) = loaded_docs
+                .iter()
+                .find(|((path, _), _)| path == file_path)?;
+            let count = missing_object_count(&source.doc, id_map);
+            (count > 0).then(|| BrokenReferences {
+                file_name: file_name_from_path(file_path),
+                file_path: file_path.clone(),
+                count,
+            })
+        })
+        .collect();
+
+    // The name it was saved under, after sanitizing and any suffix
+    let saved_path = output_path.to_string_lossy().to_string();
+    Ok(DownloadSuccessRes {
+        key: "PDF_DOWNLOAD_SUCCESS".to_string(),
+        file_name: file_name_from_path(&saved_path),
+        file_path: saved_path,
+        broken_references,
+    })
+}
+
+/// Scales the page onto the paper size of the export, turned to match it unless the orientation
+/// is fixed. With a layout, the paper size applies to the sheets instead.
+fn fit_to_paper(
+    doc: &mut Document,
+    page_id: ObjectId,
+    options: &ExportOptions,
+) -> Result<(), ErrorCode> {
+    let (Some(page_size), None) = (options.page_size, options.layout) else {
+        return Ok(());
+    };
+
+    let (mut width, mut height) = page_size.dimensions();
+    let (page_width, page_height) = page_display_size_of(doc, page_id);
+    if !options.fixed_orientation && page_width > page_height {
+        std::mem::swap(&mut width, &mut height);
+    }
+    fit_page_to_size(doc, page_id, width, height)
+}
+
+/// Adds a blank page sized like the last one when the document that ended has an odd page count.
+fn pad_to_even(
+    doc: &mut Document,
+    pages_id: ObjectId,
+    new_pages: &mut Vec<ObjectId>,
+    finished_run: Option<(&str, usize)>,
+) {
+    let (Some((_, count)), Some(&last_page)) = (finished_run, new_pages.last()) else {
+        return;
+    };
+    if count % 2 == 1 {
+        let (width, height) = page_display_size_of(doc, last_page);
+        new_pages.push(add_blank_page(doc, pages_id, width, height));
+    }
+}
+
+/// Adds the pages of a generated entry, sized like `previous_page` when the entry has no size.
+fn add_generated_page(
+    app: &AppHandle,
+    doc: &mut Document,
+    pages_id: ObjectId,
+    page: &GeneratedPage,
+    previous_page: Option<ObjectId>,
+) -> Result<Vec<ObjectId>, CommandError> {
+    let size = match page {
+        GeneratedPage::Blank { size } | GeneratedPage::Separator { size, .. } => *size,
+    };
+    let (width, height) = match (size, previous_page) {
+        (Some(size), _) => size.dimensions(),
+        (None, Some(previous_page)) => page_display_size_of(doc, previous_page),
+        (None, None) => PaperSize::A4.dimensions(),
+    };
+
+    let text = match page {
+        GeneratedPage::Blank { .. } => {
+            return Ok(vec![add_blank_page(doc, pages_id, width, height)])
+        }
+        GeneratedPage::Separator { text, .. } => text,
+    };
+
+    let rendered = render_separator_page(app, text, width, height)?;
+    let separator = Document::load_mem(&rendered)
+        .map_err(|e| ErrorCode::PdfGeneratePageError.because(e))?;
+
+    let mut id_map = BTreeMap::new();
+    separator
+        .get_pages()
+        .into_values()
+        .map(|page_id| {
+            import_page(&separator, page_id, doc, &mut id_map, pages_id, None)
+                .map_err(CommandError::new)
+        })
+        .collect()
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn change_security(
+    app: AppHandle,
+    file_name: &str,
+    file_path: &str,
+    password: Option<String>,
+    new_user_password: Option<String>,
+    new_owner_password: Option<String>,
+    encryption_level: Option<EncryptionLevel>,
+    permissions: Option<PermissionsData>,
+    destination: Option<OutputDestination>,
+) -> Result<DownloadSuccessRes, CommandError> {
+    let fail = |error: CommandError| error.with_file_name(file_name).with_file(file_path);
+
+    let destination = destination.unwrap_or_default();
+    let output_path = get_output_path(&app, file_name, &destination).map_err(fail)?;
+
+    // Load the whole document so outlines, forms and attachments survive the round trip
+    let mut doc =
+        load_document(file_path).map_err(|e| fail(ErrorCode::PdfLoadError.because(e)))?;
+
+    // Changing the security of a restricted file requires its owner password
+    let pdfium = pdfium_state(&app).map_err(fail)?;
+    check_export_permissions(&pdfium, file_path, password.as_deref()).map_err(fail)?;
+
+    if doc.is_encrypted() {
+        doc.decrypt(password.as_deref().unwrap_or("")).map_err(|_| {
+            fail(CommandError::new(if password.is_some() {
+                ErrorCode::PdfPasswordIncorrect
+            } else {
+                ErrorCode::PdfPasswordRequired
+            }))
         })?;
+        doc.trailer.remove(b"Encrypt");
+    }
+
+    // Objects from object streams were expanded on load; drop the containers so they
+    // are not written (and encrypted) a second time
+    doc.objects.retain(|_, obj| match obj.as_stream() {
+        Ok(stream) => !stream.dict.has_type(b"ObjStm") && !stream.dict.has_type(b"XRef"),
+        Err(_) => true,
+    });
+
+    ensure_document_id(&mut doc);
+
+    if new_user_password.is_some() || new_owner_password.is_some() {
+        let enc_level = encryption_level.unwrap_or(EncryptionLevel::Aes128);
+        let user_password = new_user_password.unwrap_or_default();
+        // The user password must not double as the owner password, or anyone who can open the
+        // file could lift its restrictions
+        let owner_password = new_owner_password
+            .filter(|password| !password.is_empty())
+            .unwrap_or_else(random_owner_password);
+
+        doc.version = match enc_level {
+            EncryptionLevel::Aes128 => "1.5".to_string(),
+            EncryptionLevel::Aes256 => "1.7".to_string(),
+        };
+
+        encrypt_document(
+            &mut doc,
+            &user_password,
+            &owner_password,
+            enc_level,
+            permissions_from_data(permissions),
+        )
+        .map_err(fail)?;
+    }
+
+    PendingOutput::write(&mut doc, &output_path)
+        .and_then(PendingOutput::commit)
+        .map_err(|e| e.with_file_name(file_name))?;
 
+    let saved_path = output_path.to_string_lossy().to_string();
     Ok(DownloadSuccessRes {
         key: "PDF_DOWNLOAD_SUCCESS".to_string(),
-        file_name: file_name.to_string(),
-        file_path: output_path.to_string_lossy().to_string(),
+        file_name: file_name_from_path(&saved_path),
+        file_path: saved_path,
+        broken_references: Vec::new(),
     })
 }
 
 #[tauri::command]
 pub async fn generate_thumbnails(
     app: AppHandle,
+    pdfium: State<'_, PdfiumState>,
+    jobs: State<'_, JobManager>,
+    documents: State<'_, DocumentRegistry>,
     file_path: &str,
     password: Option<String>,
     document_id: Option<String>,
-) -> Result<DocumentData, String> {
+) -> Result<DocumentData, CommandError> {
     let document_id = document_id.unwrap_or_else(|| Uuid::new_v4().to_string());
     let file_ext = get_file_ext(file_path)?;
 
     let file_name = file_name_from_path(file_path);
 
-    let mut thumbnails = Vec::new();
-
-    // User error: file not found
     if !Path::new(file_path).exists() {
-        return Ok(DocumentData {
-            id: document_id,
-            file_name,
-            file_path: file_path.to_string(),
-            thumbnails,
-            error: Some("File not found".to_string()),
-        });
+        return Err(CommandError::new(ErrorCode::FileNotFound).with_file(file_path));
     }
 
-    let pdfium = init_pdfium(&app)?;
+    let workspace = app.state::<TempWorkspace>();
+    let file_hash = workspace.source_hash(Path::new(file_path))?;
+
+    // Text and image files are converted to a temporary PDF first, which is then rendered
+    // like any other PDF
+    let render_path = if file_ext == "txt" || file_ext == "md" {
+        let temp_pdf_path = workspace
+            .convert(&file_hash, "text", |output_path| {
+                let text = fs::read_to_string(file_path)
+                    .map_err(|e| ErrorCode::PdfLoadError.because(e))?;
+                convert_text_to_pdf(&app, &text, output_path)
+            })
+            .map_err(|e| e.with_file(file_path))?;
+        temp_pdf_path.to_string_lossy().to_string()
+    } else if file_ext == "png" || file_ext == "jpg" || file_ext == "jpeg" || file_ext == "webp" {
+        let temp_pdf_path = workspace
+            .convert(&file_hash, "image", |output_path| {
+                convert_image_to_pdf(&app, file_path, output_path)
+            })
+            .map_err(|e| e.with_file(file_path))?;
+        temp_pdf_path.to_string_lossy().to_string()
+    } else {
+        file_path.to_string()
+    };
+    let is_converted = render_path != file_path;
+
+    // Password errors tell the frontend to ask for one
+    let (render_path, repair) = match pdfium.open(&render_path, password.as_deref()) {
+        Ok(_) => (render_path, None),
+        Err(e) => {
+            let error = open_error(e, password.is_some()).with_file(file_path);
+            if is_converted || error.code != ErrorCode::PdfLoadError {
+                return Err(error);
+            }
 
-    if file_ext == "txt" || file_ext == "md" {
-        // For text files, first convert to PDF
-
-        let temp_pdf_path = get_temp_pdf_path(file_path);
-        if !temp_pdf_path.exists() {
-            // If no temporary PDF exists, convert the text file
-            let text =
-                fs::read_to_string(file_path).map_err(|_| format!("PDF_LOAD_ERROR"))?;
-            convert_text_to_pdf(&app, &text, &temp_pdf_path)?;
+            // A damaged file is shown as a repaired copy, or fails as it did if it can't be
+            // repaired either
+            let (repaired_path, report) = workspace
+                .repair(&file_hash, |output_path| {
+                    repair_file(Path::new(file_path), output_path)
+                })
+                .map_err(|_| error)?;
+            (repaired_path.to_string_lossy().to_string(), Some(report))
         }
+    };
+    let document = pdfium
+        .open(&render_path, password.as_deref())
+        .map_err(|e| open_error(e, password.is_some()).with_file(file_path))?;
+
+    let page_count = document.pages().len() as usize;
+    // Owner-password-only files open without a password but may still be restricted
+    let permissions = if is_converted {
+        None
+    } else {
+        Some(get_document_permissions(&document))
+    };
+    drop(document);
+
+    let registered = RegisteredDocument {
+        render_path: render_path.clone(),
+        // Never write thumbnails of password-protected documents to disk
+        file_hash: if password.is_none() { Some(file_hash.clone()) } else { None },
+        password,
+        source_path: file_path.to_string(),
+        source_hash: Some(file_hash),
+    };
+    documents.register(&document_id, registered.clone());
+    if render_path != file_path {
+        workspace.track(&document_id, Path::new(&render_path));
+    }
 
-        let document = pdfium
-            .load_pdf_from_file(temp_pdf_path.to_str().unwrap(), None)
-            .map_err(|_| format!("PDF_LOAD_ERROR"))?;
+    // The document id doubles as the job id, so re-rendering a document cancels its previous render
+    let job = jobs.start(Some(document_id.clone()));
+    job.set_total(page_count);
+    let task_app = app.clone();
+    let task_document_id = document_id.clone();
+
+    // Page urls are sent to the frontend one by one, once the page is in the disk cache
+    tauri::async_runtime::spawn_blocking(move || {
+        let result = render_thumbnails(&task_app, &task_document_id, &registered, &job);
+
+        task_app.state::<JobManager>().finish(&job);
+        task_app.state::<ThumbnailCache>().enforce_size_limit();
+
+        let _ = task_app.emit(
+            "thumbnails-finished",
+            ThumbnailsFinishedEvent {
+                document_id: task_document_id,
+                cancelled: job.is_cancelled(),
+                error: result.err(),
+            },
+        );
+    });
+
+    Ok(DocumentData {
+        id: document_id,
+        file_name,
+        file_path: render_path,
+        source_path: file_path.to_string(),
+        thumbnails: vec![],
+        page_count,
+        permissions,
+        repair,
+    })
+}
+
+fn render_thumbnails(
+    app: &AppHandle,
+    document_id: &str,
+    registered: &RegisteredDocument,
+    job: &JobHandle,
+) -> Result<(), CommandError> {
+    let pdfium = pdfium_state(app)?;
+    let cache = app.state::<ThumbnailCache>().inner();
+    let render_pool = app.state::<RenderPool>();
+    // Read once, so every page of this render ends up in the cache under the same encoding
+    let encoding = app.state::<RenderSettings>().get().thumbnails;
+
+    // Wait for a free slot so that opening many files at once doesn't thrash the machine
+    let _permit = render_pool.acquire();
+    let encoders = render_pool.encoders();
+
+    // Usually still open from reading the document metadata
+    let document = pdfium
+        .open(&registered.render_path, registered.password.as_deref())
+        .map_err(|e| {
+            open_error(e, registered.password.is_some()).with_file(&registered.render_path)
+        })?;
+    let total = document.pages().len() as usize;
+
+    let emit_thumbnail = |page_index: usize, width: u32, height: u32| {
+        let _ = app.emit(
+            "thumbnail-ready",
+            ThumbnailReadyEvent {
+                document_id: document_id.to_string(),
+                thumbnail: ThumbnailData {
+                    id: format!("{}_{}", document_id, page_index),
+                    page_index,
+                    file_path: registered.render_path.clone(),
+                    thumbnail: page_url(document_id, page_index, &format!("h={}", THUMBNAIL_HEIGHT)),
+                    width,
+                    height,
+                },
+                rendered: job.advance().done,
+                total,
+            },
+        );
+    };
+    let error: Mutex<Option<CommandError>> = Mutex::new(None);
 
+    // Pages are rendered one after another on this thread, while encoding runs on the pool.
+    // Thumbnails may therefore arrive out of page order.
+    encoders.in_place_scope(|scope| {
         for (page_index, page) in document.pages().iter().enumerate() {
-            let aspect_ratio = page.width().value / page.height().value;
+            if job.is_cancelled() {
+                break;
+            }
 
-            let target_height: f32 = 250.0;
-            let target_width = target_height * aspect_ratio;
+            // Pages that can't be cached are rendered on demand when the frontend requests them
+            let Some(key) = thumbnail_cache_key(registered.file_hash.as_deref(), page_index, encoding) else {
+                let (page_width, page_height) = page_display_size(&page);
+                let aspect_ratio = page_width / page_height;
+                emit_thumbnail(page_index, (THUMBNAIL_HEIGHT * aspect_ratio) as u32, THUMBNAIL_HEIGHT as u32);
+                continue;
+            };
+
+            if let Some((_, width, height)) = cache.get(&key) {
+                emit_thumbnail(page_index, width, height);
+                continue;
+            }
 
-            let bitmap = gen_image_bitmap(&page, target_width, target_height, ImageQuality::Low)?;
+            let image = match render_thumbnail_image(&page) {
+                Ok(image) => image,
+                Err(e) => {
+                    *lock(&error) = Some(e.with_page(page_index));
+                    break;
+                }
+            };
 
-            let data_url = gen_image_data_url(&bitmap)?;
+            let emit_thumbnail = &emit_thumbnail;
+            let error = &error;
+            scope.spawn(move |_| {
+                if job.is_cancelled() {
+                    return;
+                }
 
-            thumbnails.push(ThumbnailData {
-                id: format!("{}_{}", document_id, page_index),
-                page_index: page_index,
-                file_path: temp_pdf_path.to_string_lossy().to_string(),
-                thumbnail: data_url,
-                width: bitmap.width() as u32,
-                height: bitmap.height() as u32,
+                match encode_image(&image, encoding) {
+                    Ok(buffer) => {
+                        cache.put(&key, &buffer);
+                        emit_thumbnail(page_index, image.width(), image.height());
+                    }
+                    Err(e) => {
+                        lock(&error).get_or_insert(e.with_page(page_index));
+                    }
+                }
             });
         }
+    });
 
-        Ok(DocumentData {
-            id: document_id,
-            file_name,
-            file_path: temp_pdf_path.to_string_lossy().to_string(),
-            thumbnails,
-            error: None,
-        })
-    } else if file_ext == "png" || file_ext == "jpg" || file_ext == "jpeg" || file_ext == "webp" {
-        // Create a temporary PDF file for the image
-        let temp_pdf_path = get_temp_pdf_path(&document_id);
-        convert_image_to_pdf(&app, file_path, &temp_pdf_path)?;
+    match error.into_inner().unwrap_or_else(PoisonError::into_inner) {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
 
-        let document = pdfium
-            .load_pdf_from_file(temp_pdf_path.to_str().unwrap(), None)
-            .map_err(|_| format!("PDF_LOAD_ERROR"))?;
+#[tauri::command]
+pub async fn set_render_concurrency(
+    render_pool: State<'_, RenderPool>,
+    limit: usize,
+) -> Result<(), CommandError> {
+    render_pool.set_limit(limit)
+}
 
-        for (page_index, page) in document.pages().iter().enumerate() {
-            let aspect_ratio = page.width().value / page.height().value;
+#[tauri::command]
+pub async fn get_render_settings(
+    settings: State<'_, RenderSettings>,
+) -> Result<RenderSettingsData, CommandError> {
+    Ok(settings.get())
+}
 
-            // Calculate target width based on max height and aspect ratio
-            let target_height: f32 = 250.0;
-            let target_width = target_height * aspect_ratio;
+#[tauri::command]
+pub async fn set_render_settings(
+    settings: State<'_, RenderSettings>,
+    render_settings: RenderSettingsData,
+) -> Result<(), CommandError> {
+    settings.set(render_settings);
+    Ok(())
+}
 
-            let bitmap = gen_image_bitmap(&page, target_width, target_height, ImageQuality::Low)?;
+#[tauri::command]
+pub async fn cancel_job(
+    jobs: State<'_, JobManager>,
+    job_id: String,
+) -> Result<bool, CommandError> {
+    Ok(jobs.cancel(&job_id))
+}
 
-            let data_url = gen_image_data_url(&bitmap)?;
+/// Forgets a document the user removed: stops its render and deletes the temporary files only it
+/// used.
+#[tauri::command]
+pub async fn close_document(
+    pdfium: State<'_, PdfiumState>,
+    jobs: State<'_, JobManager>,
+    documents: State<'_, DocumentRegistry>,
+    workspace: State<'_, TempWorkspace>,
+    document_id: String,
+) -> Result<(), CommandError> {
+    jobs.cancel(&document_id);
+    documents.unregister(&document_id);
+    for path in workspace.release(&document_id) {
+        pdfium.close(&path.to_string_lossy());
+        // Fails on Windows while a render still has the file open; it goes when the app exits
+        let _ = fs::remove_file(&path);
+    }
+    Ok(())
+}
 
-            thumbnails.push(ThumbnailData {
-                id: format!("{}_{}", document_id, page_index),
-                page_index: page_index,
-                file_path: temp_pdf_path.to_string_lossy().to_string(),
-                thumbnail: data_url,
-                width: bitmap.width() as u32,
-                height: bitmap.height() as u32,
-            });
-        }
+#[tauri::command]
+pub async fn get_job_progress(
+    jobs: State<'_, JobManager>,
+    job_id: String,
+) -> Result<Option<JobProgress>, CommandError> {
+    Ok(jobs.progress(&job_id))
+}
 
-        Ok(DocumentData {
-            id: document_id,
-            file_name,
-            file_path: temp_pdf_path.to_string_lossy().to_string(),
-            thumbnails,
-            error: None,
-        })
+/// The file the protocol renders for a full resolution view: text files go through their temporary PDF.
+fn full_res_render_path(
+    app: &AppHandle,
+    file_path: &str,
+    file_ext: &str,
+) -> Result<String, CommandError> {
+    if file_ext == "txt" || file_ext == "md" {
+        // Usually converted already when the document was opened
+        let workspace = app.state::<TempWorkspace>();
+        let file_hash = workspace.source_hash(Path::new(file_path))?;
+        let temp_pdf_path = workspace
+            .convert(&file_hash, "text", |output_path| {
+                let text = fs::read_to_string(file_path)
+                    .map_err(|e| ErrorCode::PdfLoadError.because(e))?;
+                convert_text_to_pdf(app, &text, output_path)
+            })
+            .map_err(|e| e.with_file(file_path))?;
+        Ok(temp_pdf_path.to_string_lossy().to_string())
     } else {
-        let document: Result<PdfDocument, DocumentData> = match &password {
-            Some(pw) => match pdfium.load_pdf_from_file(file_path, Some(pw)) {
-                Ok(doc) => Ok(doc),
-                Err(e) => {
-                    let msg = e.to_string().to_lowercase();
-                    if msg.contains("password") || msg.contains("encrypted") {
-                        Err(DocumentData {
-                            id: document_id.clone(),
-                            file_name: file_name.clone(),
-                            file_path: file_path.to_string(),
-                            thumbnails: vec![],
-                            error: Some("PDF_PASSWORD_INCORRECT".to_string()),
-                        })
-                    } else {
-                        return Err(format!("PDF_LOAD_ERROR"));
-                    }
-                }
-            },
-            None => match pdfium.load_pdf_from_file(file_path, None) {
-                Ok(doc) => Ok(doc),
-                Err(e) => {
-                    let msg = e.to_string().to_lowercase();
-                    if msg.contains("password") || msg.contains("encrypted") {
-                        Err(DocumentData {
-                            id: document_id.clone(),
-                            file_name: file_name.clone(),
-                            file_path: file_path.to_string(),
-                            thumbnails: vec![],
-                            error: Some("PDF_PASSWORD_REQUIRED".to_string()),
-                        })
-                    } else {
-                        return Err(format!("PDF_LOAD_ERROR"));
-                    }
-                }
-            },
-        };
+        Ok(file_path.to_string())
+    }
+}
 
-        let document = match document {
-            Ok(doc) => doc,
-            Err(doc_data) => return Ok(doc_data),
-        };
+/// Page size in points. Images are converted at one point per pixel.
+fn page_size(
+    pdfium: &PdfiumState,
+    render_path: &str,
+    file_ext: &str,
+    page_index: usize,
+    password: Option<&str>,
+) -> Result<(f32, f32), CommandError> {
+    if file_ext == "png" || file_ext == "jpg" || file_ext == "jpeg" || file_ext == "webp" {
+        let (width, height) = image::image_dimensions(render_path)
+            .map_err(|e| ErrorCode::PdfLoadError.because(e).with_file(render_path))?;
 
-        for (page_index, page) in document.pages().iter().enumerate() {
-            let aspect_ratio = page.width().value / page.height().value;
+        return Ok((width as f32, height as f32));
+    }
 
-            let target_height: f32 = 250.0;
-            let target_width = target_height * aspect_ratio;
+    let document = pdfium
+        .open(render_path, password)
+        .map_err(|e| open_error(e, password.is_some()).with_file(render_path))?;
 
-            let bitmap = gen_image_bitmap(&page, target_width, target_height, ImageQuality::Low)?;
+    let page = document.pages().get(page_index as u16).map_err(|_| {
+        CommandError::new(ErrorCode::PdfPageNotFound)
+            .with_file(render_path)
+            .with_page(page_index)
+    })?;
 
-            // Convert to base64
-            let mut buffer = Vec::new();
-            let img = bitmap.as_image();
+    Ok(page_display_size(&page))
+}
 
-            img.write_to(&mut Cursor::new(&mut buffer), image::ImageFormat::Png)
-                .map_err(|_| format!("PDF_LOAD_ERROR"))?;
+#[tauri::command]
+pub async fn gen_full_res(
+    app: AppHandle,
+    pdfium: State<'_, PdfiumState>,
+    documents: State<'_, DocumentRegistry>,
+    file_path: &str,
+    page_index: usize,
+    password: Option<String>,
+) -> Result<FullImageData, CommandError> {
+    let file_ext = get_file_ext(file_path)?;
+    let render_path = full_res_render_path(&app, file_path, &file_ext)?;
 
-            let data_url = gen_data_url_from_buffer(&buffer);
+    let document_id = documents.register_path(&render_path, password.as_deref());
+    let url = page_url(&document_id, page_index, "q=high");
 
-            thumbnails.push(ThumbnailData {
-                id: format!("{}_{}", document_id, page_index),
-                page_index: page_index,
-                file_path: file_path.to_string(),
-                thumbnail: data_url,
-                width: bitmap.width() as u32,
-                height: bitmap.height() as u32,
-            });
-        }
+    let (page_width, page_height) =
+        page_size(&pdfium, &render_path, &file_ext, page_index, password.as_deref())?;
 
-        Ok(DocumentData {
-            id: document_id,
-            file_name,
-            file_path: file_path.to_string(),
-            thumbnails,
-            error: None,
-        })
+    if file_ext == "png" || file_ext == "jpg" || file_ext == "jpeg" || file_ext == "webp" {
+        return Ok(FullImageData {
+            url,
+            width: page_width as u32,
+            height: page_height as u32,
+            page_width,
+            page_height,
+        });
     }
+
+    // Matches the size the protocol renders at for high quality
+    let (width, height) = gen_render_size(page_width, page_height, ImageQuality::High);
+
+    Ok(FullImageData {
+        url,
+        width: width as u32,
+        height: height as u32,
+        page_width,
+        page_height,
+    })
 }
 
+/// Renders one tile of a page for deep zoom. The scale is `dpi / 72` if a DPI is given, else `zoom`
+/// (pixels per point), and is lowered if the tile would exceed the pixel budget.
 #[tauri::command]
-pub async fn gen_full_res(
+#[allow(clippy::too_many_arguments)]
+pub async fn render_region(
     app: AppHandle,
+    pdfium: State<'_, PdfiumState>,
+    documents: State<'_, DocumentRegistry>,
     file_path: &str,
     page_index: usize,
     password: Option<String>,
-) -> Result<FullImageData, String> {
+    region: RegionRect,
+    zoom: Option<f32>,
+    dpi: Option<f32>,
+) -> Result<RegionImageData, CommandError> {
     let file_ext = get_file_ext(file_path)?;
-    let pdfium = init_pdfium(&app)?;
+    let render_path = full_res_render_path(&app, file_path, &file_ext)?;
 
-    if file_ext == "txt" || file_ext == "md" {
-        // Check if we have a temporary PDF file
-        let temp_pdf_path = get_temp_pdf_path(file_path);
-        if !temp_pdf_path.exists() {
-            // If no temporary PDF exists, convert the text file
-            let text =
-                fs::read_to_string(file_path).map_err(|_| format!("PDF_LOAD_ERROR"))?;
-            convert_text_to_pdf(&app, &text, &temp_pdf_path)?;
-        }
-        
-        let document = pdfium
-            .load_pdf_from_file(temp_pdf_path.to_str().unwrap(), None)
-            .map_err(|_| format!("PDF_LOAD_ERROR"))?;
-
-        let page = document
-            .pages()
-            .get(page_index as u16)
-            .map_err(|_| format!("PDF_PAGE_NOT_FOUND"))?;
-
-        let bitmap = gen_image_bitmap(&page, page.width().value, page.height().value, ImageQuality::High)?;
-        let data_url = gen_image_data_url(&bitmap)?;
-
-        Ok(FullImageData {
-            data_url,
-            width: bitmap.width() as u32,
-            height: bitmap.height() as u32,
-        })
-    } else if file_ext == "png" || file_ext == "jpg" || file_ext == "jpeg" || file_ext == "webp" {
-        let image = image::open(file_path).map_err(|e| format!("Failed to open image: {}", e))?;
-        let mut buffer = Vec::new();
+    let (page_width, page_height) =
+        page_size(&pdfium, &render_path, &file_ext, page_index, password.as_deref())?;
+
+    let requested_scale = match (dpi, zoom) {
+        (Some(dpi), _) => dpi / 72.0,
+        (None, Some(zoom)) => zoom,
+        (None, None) => 1.0,
+    };
+    let (region, scale) = fit_region(page_width, page_height, region, requested_scale)
+        .ok_or_else(|| {
+            CommandError::new(ErrorCode::PdfRegionInvalid)
+                .with_file(file_path)
+                .with_page(page_index)
+        })?;
+    let (width, height) = region_pixel_size(region, scale);
+
+    let document_id = documents.register_path(&render_path, password.as_deref());
+    let url = page_url(
+        &document_id,
+        page_index,
+        &format!(
+            "region={},{},{},{}&scale={}",
+            region.x, region.y, region.width, region.height, scale
+        ),
+    );
+
+    Ok(RegionImageData {
+        url,
+        region,
+        scale,
+        width,
+        height,
+    })
+}
 
-        image
-            .write_to(&mut Cursor::new(&mut buffer), image::ImageFormat::WebP)
-            .map_err(|_| format!("PDF_LOAD_ERROR"))?;
+#[tauri::command]
+pub async fn save_project(
+    file_path: &str,
+    project: ProjectData,
+) -> Result<DownloadSuccessRes, CommandError> {
+    let path = project_path(file_path);
+    let file_name = file_name_from_path(&path.to_string_lossy());
 
-        Ok(FullImageData {
-            data_url: gen_data_url_from_buffer(&buffer),
-            width: image.width() as u32,
-            height: image.height() as u32,
-        })
-    } else {
-        let document = pdfium
-            .load_pdf_from_file(file_path, password.as_deref())
-            .map_err(|_| format!("PDF_LOAD_ERROR"))?;
-
-        let page = document
-            .pages()
-            .get(page_index as u16)
-            .map_err(|_| format!("PDF_PAGE_NOT_FOUND"))?;
-
-        let bitmap = gen_image_bitmap(&page, page.width().value, page.height().value, ImageQuality::High)?;
-        let data_url = gen_image_data_url(&bitmap)?;
-
-        Ok(FullImageData {
-            data_url,
-            width: bitmap.width() as u32,
-            height: bitmap.height() as u32,
-        })
+    write_project(&path, project)?;
+
+    Ok(DownloadSuccessRes {
+        key: "PROJECT_SAVE_SUCCESS".to_string(),
+        file_name,
+        file_path: path.to_string_lossy().to_string(),
+        broken_references: Vec::new(),
+    })
+}
+
+#[tauri::command]
+pub async fn open_project(file_path: &str) -> Result<ProjectOpenRes, CommandError> {
+    let project = read_project(Path::new(file_path))?;
+    let sources = check_sources(&project);
+
+    Ok(ProjectOpenRes { project, sources })
+}
+
+#[tauri::command]
+pub async fn list_recipes(
+    recipes: State<'_, RecipeStore>,
+    watcher: State<'_, RecipeWatcher>,
+) -> Result<RecipeListRes, CommandError> {
+    Ok(RecipeListRes {
+        recipes: recipes.list(),
+        watched: watcher.watched(),
+    })
+}
+
+#[tauri::command]
+pub async fn save_recipe(
+    recipes: State<'_, RecipeStore>,
+    recipe: MergeRecipe,
+) -> Result<MergeRecipe, CommandError> {
+    recipes.save(recipe)
+}
+
+#[tauri::command]
+pub async fn delete_recipe(
+    recipes: State<'_, RecipeStore>,
+    watcher: State<'_, RecipeWatcher>,
+    recipe_id: String,
+) -> Result<bool, CommandError> {
+    watcher.unwatch(&recipe_id);
+    recipes.delete(&recipe_id)
+}
+
+#[tauri::command]
+pub async fn run_recipe(
+    app: AppHandle,
+    jobs: State<'_, JobManager>,
+    recipes: State<'_, RecipeStore>,
+    recipe_id: String,
+    password: Option<String>,
+    job_id: Option<String>,
+) -> Result<DownloadSuccessRes, CommandError> {
+    let recipe = recipes
+        .get(&recipe_id)
+        .ok_or_else(|| CommandError::new(ErrorCode::RecipeNotFound))?;
+
+    let job = jobs.start(job_id);
+    let result = run_recipe_job(&app, &job, &recipe, password, &[], OverwritePolicy::Unique);
+    jobs.finish(&job);
+    result
+}
+
+/// Re-runs the recipe whenever its inputs change, until `unwatch_recipe` is called. Every run
+/// emits a `recipe-run` event with its result.
+#[tauri::command]
+pub async fn watch_recipe(
+    app: AppHandle,
+    recipes: State<'_, RecipeStore>,
+    watcher: State<'_, RecipeWatcher>,
+    recipe_id: String,
+    password: Option<String>,
+) -> Result<(), CommandError> {
+    let recipe = recipes
+        .get(&recipe_id)
+        .ok_or_else(|| CommandError::new(ErrorCode::RecipeNotFound))?;
+    check_recipe_password(&recipe, password.as_deref())?;
+
+    watcher.watch(&app, &recipe, password);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn unwatch_recipe(
+    watcher: State<'_, RecipeWatcher>,
+    recipe_id: String,
+) -> Result<bool, CommandError> {
+    Ok(watcher.unwatch(&recipe_id))
+}
+
+/// Merges the files the recipe currently resolves to, skipping those in `exclude`, into the
+/// downloads folder. `overwrite` decides what happens to an earlier output of the same name.
+pub(crate) fn run_recipe_job(
+    app: &AppHandle,
+    job: &JobHandle,
+    recipe: &MergeRecipe,
+    password: Option<String>,
+    exclude: &[PathBuf],
+    overwrite: OverwritePolicy,
+) -> Result<DownloadSuccessRes, CommandError> {
+    let fail = |error: CommandError| error.with_file_name(&recipe.file_name);
+
+    check_recipe_password(recipe, password.as_deref())?;
+    let files = resolve_recipe(recipe, exclude).map_err(fail)?;
+    let (entries, temp_files) = recipe_entries(app, &files).map_err(fail)?;
+
+    let result = merge_pages(
+        app,
+        job,
+        &recipe.file_name,
+        &entries,
+        password,
+        recipe.encryption_level,
+        &recipe.export_options,
+        &OutputDestination {
+            overwrite,
+            ..OutputDestination::default()
+        },
+    );
+    remove_temp_files(app, &temp_files);
+    result
+}
+
+/// A recipe that encrypts its output cannot run without the password, which is never saved.
+fn check_recipe_password(recipe: &MergeRecipe, password: Option<&str>) -> Result<(), CommandError> {
+    if recipe.encryption_level.is_some() && password.is_none_or(str::is_empty) {
+        return Err(CommandError::new(ErrorCode::RecipePasswordRequired)
+            .with_file_name(&recipe.file_name));
     }
+    Ok(())
 }
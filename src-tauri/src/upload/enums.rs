@@ -1,5 +1,142 @@
+use crate::upload::structs::ThumbnailDownloadData;
+
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub(crate) enum ImageQuality {
   Low,
   High
-}
\ No newline at end of file
+}
+
+#[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum OutputFormat {
+  Png,
+  WebpLossless,
+  WebpLossy,
+  Jpeg,
+  Avif
+}
+
+impl OutputFormat {
+  pub(crate) fn mime_type(self) -> &'static str {
+    match self {
+      OutputFormat::Png => "image/png",
+      OutputFormat::WebpLossless | OutputFormat::WebpLossy => "image/webp",
+      OutputFormat::Jpeg => "image/jpeg",
+      OutputFormat::Avif => "image/avif",
+    }
+  }
+
+  pub(crate) fn extension(self) -> &'static str {
+    match self {
+      OutputFormat::Png => "png",
+      OutputFormat::WebpLossless | OutputFormat::WebpLossy => "webp",
+      OutputFormat::Jpeg => "jpg",
+      OutputFormat::Avif => "avif",
+    }
+  }
+
+  pub(crate) fn is_lossy(self) -> bool {
+    matches!(self, OutputFormat::WebpLossy | OutputFormat::Jpeg | OutputFormat::Avif)
+  }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum PageBox {
+  Media,
+  Crop,
+  Bleed,
+  Trim,
+  Art
+}
+
+#[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum PaperSize {
+  A3,
+  A4,
+  A5,
+  Letter,
+  Legal,
+  Tabloid
+}
+
+impl PaperSize {
+  /// Portrait width and height in points.
+  pub(crate) fn dimensions(self) -> (f32, f32) {
+    match self {
+      PaperSize::A3 => (841.89, 1190.55),
+      PaperSize::A4 => (595.28, 841.89),
+      PaperSize::A5 => (419.53, 595.28),
+      PaperSize::Letter => (612.0, 792.0),
+      PaperSize::Legal => (612.0, 1008.0),
+      PaperSize::Tabloid => (792.0, 1224.0),
+    }
+  }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(tag = "type")]
+pub enum PageLayout {
+  // Several pages per sheet, left to right and top to bottom; gap and border are in points
+  NUp {
+    columns: u32,
+    rows: u32,
+    #[serde(default)]
+    gap: f32,
+    #[serde(default)]
+    border: f32
+  },
+  // Two pages per side, ordered so the stacked sheets read in sequence once folded
+  Booklet
+}
+
+// Pages of an export that do not come from a source document
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum GeneratedPage {
+  // Sized like the page before it when no size is given
+  Blank { size: Option<PaperSize> },
+  // One paragraph per line of text, the first one set as a heading
+  Separator { text: String, size: Option<PaperSize> }
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(untagged)]
+pub enum ExportEntry {
+  Page(ThumbnailDownloadData),
+  Generated(GeneratedPage)
+}
+
+#[derive(serde::Serialize, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum SourceState {
+  Ok,
+  Missing,
+  // The content changed since the project was saved, so its pages may no longer match
+  Modified
+}
+
+// How the files matching one recipe input are ordered
+#[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum InputOrder {
+  #[default]
+  Name,
+  Modified
+}
+
+// Parts of a source PDF that do not survive, or may stop working, when its pages are merged
+#[derive(serde::Serialize, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum PdfFeature {
+  Forms,
+  JavaScript,
+  Signatures,
+  Attachments,
+  Links
+}
+
+// What an export does when a file with its name already exists
+#[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum OverwritePolicy {
+  // Adds `_0`, `_1`, ... to the name
+  #[default]
+  Unique,
+  Overwrite,
+  Fail
+}
@@ -0,0 +1,163 @@
+use std::fmt::Display;
+use std::path::Path;
+
+use crate::upload::functions::file_name_from_path;
+
+/// What went wrong, independent of where. Serialized as a stable code such as `PDF_LOAD_ERROR`,
+/// which the frontend uses as its translation key; the messages are only for logs.
+#[derive(serde::Serialize, thiserror::Error, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    // Setup
+    #[error("the PDF rendering library could not be loaded")]
+    PdfiumInitError,
+    #[error("the render pool could not be created")]
+    RenderPoolError,
+    #[error("a bundled resource could not be found")]
+    ResourceMissing,
+
+    // Reading sources
+    #[error("file not found")]
+    FileNotFound,
+    #[error("the file changed since it was opened")]
+    FileModified,
+    #[error("unsupported file type")]
+    FileTypeUnsupported,
+    #[error("the file could not be loaded")]
+    PdfLoadError,
+    #[error("the file could not be converted to PDF")]
+    PdfConvertError,
+    #[error("a password is required")]
+    PdfPasswordRequired,
+    #[error("the password is incorrect")]
+    PdfPasswordIncorrect,
+    #[error("the security handler is not supported")]
+    PdfSecurityUnsupported,
+    #[error("the document does not allow its pages to be copied")]
+    PdfPermissionDenied,
+    #[error("the pages could not be loaded")]
+    PdfPageLoadError,
+    #[error("the pages could not be decrypted")]
+    PdfPageDecryptError,
+    #[error("page not found")]
+    PdfPageNotFound,
+
+    // Rendering
+    #[error("the page could not be rendered")]
+    PdfRenderError,
+    #[error("the image could not be encoded")]
+    ImageEncodeError,
+    #[error("the region does not lie on the page")]
+    PdfRegionInvalid,
+    #[error("unknown resource")]
+    ResourceNotFound,
+
+    // Exporting
+    #[error("the page could not be laid out")]
+    PdfPageLayoutError,
+    #[error("the layout is invalid")]
+    PdfLayoutInvalid,
+    #[error("the page could not be generated")]
+    PdfGeneratePageError,
+    #[error("the font for the table of contents could not be loaded")]
+    PdfTocFontError,
+    #[error("no output path could be chosen")]
+    PdfOutputPathError,
+    #[error("a file with the output name already exists")]
+    PdfOutputExists,
+    #[error("the document could not be encrypted")]
+    PdfEncryptError,
+    #[error("the document could not be saved")]
+    PdfSaveError,
+    #[error("cancelled")]
+    PdfDownloadCancelled,
+
+    // Projects
+    #[error("the project could not be saved")]
+    ProjectSaveError,
+    #[error("the project could not be loaded")]
+    ProjectLoadError,
+    #[error("the file is not a valid project")]
+    ProjectInvalid,
+    #[error("the project was saved by a newer version")]
+    ProjectVersionUnsupported,
+
+    // Recipes
+    #[error("the recipes could not be saved")]
+    RecipeSaveError,
+    #[error("recipe not found")]
+    RecipeNotFound,
+    #[error("the input pattern is invalid")]
+    RecipeInputInvalid,
+    #[error("an input file is missing")]
+    RecipeInputMissing,
+    #[error("the folder could not be read")]
+    RecipeFolderError,
+    #[error("no files match the recipe")]
+    RecipeNoInputs,
+    #[error("the recipe encrypts its output and needs a password")]
+    RecipePasswordRequired,
+}
+
+/// The error every command returns: what went wrong, the file and page it is about, and the
+/// underlying cause if there is one.
+#[derive(serde::Serialize, thiserror::Error, Clone, Debug)]
+#[error("{code}{}", .cause.as_ref().map(|cause| format!(": {}", cause)).unwrap_or_default())]
+pub struct CommandError {
+    pub code: ErrorCode,
+    // The file being worked on, e.g. the output of an export, which may differ from `file_path`
+    pub file_name: Option<String>,
+    pub file_path: Option<String>,
+    pub page_index: Option<usize>,
+    pub cause: Option<String>,
+}
+
+impl CommandError {
+    pub(crate) fn new(code: ErrorCode) -> Self {
+        Self {
+            code,
+            file_name: None,
+            file_path: None,
+            page_index: None,
+            cause: None,
+        }
+    }
+
+    /// The name is taken from the path, unless it was set before.
+    pub(crate) fn with_file(mut self, file_path: impl AsRef<Path>) -> Self {
+        let file_path = file_path.as_ref().to_string_lossy().to_string();
+        if self.file_name.is_none() {
+            self.file_name = Some(file_name_from_path(&file_path));
+        }
+        self.file_path = Some(file_path);
+        self
+    }
+
+    pub(crate) fn with_file_name(mut self, file_name: &str) -> Self {
+        self.file_name = Some(file_name.to_string());
+        self
+    }
+
+    pub(crate) fn with_page(mut self, page_index: usize) -> Self {
+        self.page_index = Some(page_index);
+        self
+    }
+
+    pub(crate) fn with_cause(mut self, cause: impl Display) -> Self {
+        self.cause = Some(cause.to_string());
+        self
+    }
+}
+
+impl From<ErrorCode> for CommandError {
+    fn from(code: ErrorCode) -> Self {
+        Self::new(code)
+    }
+}
+
+impl ErrorCode {
+    /// Shorthand for `CommandError::new(self).with_cause(cause)`.
+    pub(crate) fn because(self, cause: impl Display) -> CommandError {
+        CommandError::new(self).with_cause(cause)
+    }
+}
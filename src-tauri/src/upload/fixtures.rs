@@ -0,0 +1,62 @@
+//! Documents for the unit tests: small ones built in memory, and the damaged files kept in
+//! `tests/fixtures`.
+
+use lopdf::xref::XrefType;
+use lopdf::{dictionary, Document, Object, Stream};
+use std::path::PathBuf;
+
+/// A document with `page_count` US Letter pages, each showing its number.
+pub(crate) fn sample_document(page_count: usize) -> Document {
+    let mut doc = Document::with_version("1.7");
+    let pages_id = doc.new_object_id();
+    let font_id = doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+    });
+
+    let kids: Vec<Object> = (0..page_count)
+        .map(|index| {
+            let content = format!("BT /F1 24 Tf 72 720 Td ({}) Tj ET", index + 1);
+            let content_id = doc.add_object(Stream::new(dictionary! {}, content.into_bytes()));
+            let page_id = doc.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => pages_id,
+                "Contents" => content_id,
+            });
+            Object::Reference(page_id)
+        })
+        .collect();
+
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => page_count as i64,
+            "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+            "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+        }),
+    );
+    let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+    doc.trailer.set("Root", catalog_id);
+    doc
+}
+
+/// The bytes of `sample_document`, written with a cross-reference table rather than a stream so
+/// that tests can damage its entries as text.
+pub(crate) fn sample_bytes(page_count: usize) -> Vec<u8> {
+    let mut doc = sample_document(page_count);
+    doc.reference_table.cross_reference_type = XrefType::CrossReferenceTable;
+    let mut buffer = Vec::new();
+    doc.save_to(&mut buffer).expect("sample document saves");
+    buffer
+}
+
+/// The path of a file in `tests/fixtures`.
+pub(crate) fn fixture_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name)
+}
@@ -1,16 +1,54 @@
-use base64::Engine;
-use genpdf::{elements, fonts, Document as GenDocument, SimplePageDecorator};
+use genpdf::{
+    elements, fonts, style, Alignment, Document as GenDocument, Element, SimplePageDecorator,
+};
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
 use image::{DynamicImage, EncodableLayout, GenericImageView};
-use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+use lopdf::encryption::crypt_filters::{Aes128CryptFilter, Aes256CryptFilter, CryptFilter};
+use lopdf::encryption::Permissions;
+use lopdf::{Dictionary, Document, EncryptionState, EncryptionVersion, Object, ObjectId, Stream};
 use pdfium_render::prelude::*;
+use rand::distr::Alphanumeric;
+use rand::Rng;
 use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::panic;
+use std::sync::Arc;
 use std::{
+    fs,
     io::Cursor,
     path::{Path, PathBuf},
 };
 use tauri::{AppHandle, Manager};
+use uuid::Uuid;
+
+use crate::upload::boxes::page_display_size;
+use crate::upload::cache::ThumbnailCacheKey;
+use crate::upload::enums::{ImageQuality, OutputFormat, OverwritePolicy};
+use crate::upload::error::{CommandError, ErrorCode};
+use crate::upload::pdfium::PdfiumState;
+use crate::upload::repair::repair_document;
+use crate::upload::structs::{
+    DocumentPermissions, EncryptionLevel, ImageEncoding, OutputDestination, PermissionsData,
+    RegionRect,
+};
+
+pub(crate) const THUMBNAIL_HEIGHT: f32 = 250.0;
+
+// 4096 x 4096, about 64 MB of RGBA per tile
+const MAX_REGION_PIXELS: f32 = 16_777_216.0;
+
+// Not allowed in Windows file names, and kept out everywhere so exports can be copied there
+const ILLEGAL_FILE_NAME_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+// Device names Windows reserves, with or without an extension
+const RESERVED_FILE_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
 
-use crate::upload::enums::ImageQuality;
+// Leaves room for `_n` and `.pdf` within the 255 bytes most file systems allow
+const MAX_FILE_STEM_CHARS: usize = 200;
 
 pub(crate) fn clone_with_dependencies(
     doc: &Document,
@@ -25,23 +63,25 @@ pub(crate) fn clone_with_dependencies(
     let new_id = new_doc.new_object_id();
     id_map.insert(obj_id, new_id);
 
-    let obj = doc.get_object(obj_id).unwrap();
-    let cloned = match obj {
-        Object::Dictionary(dict) => {
+    let cloned = match doc.get_object(obj_id) {
+        Ok(Object::Dictionary(dict)) => {
             let mut new_dict = Dictionary::new();
             for (k, v) in dict.iter() {
                 new_dict.set(k.as_bytes(), clone_object(v, doc, new_doc, id_map));
             }
             Object::Dictionary(new_dict)
         }
-        Object::Stream(stream) => {
+        Ok(Object::Stream(stream)) => {
             let mut new_dict = Dictionary::new();
             for (k, v) in stream.dict.iter() {
                 new_dict.set(k.as_bytes(), clone_object(v, doc, new_doc, id_map));
             }
             Object::Stream(lopdf::Stream::new(new_dict, stream.content.clone()))
         }
-        other => other.clone(),
+        Ok(other) => other.clone(),
+        // A reference to a missing object means null (PDF 32000-1, 7.3.10), so one broken link
+        // in a damaged file costs that value instead of the whole export
+        Err(_) => Object::Null,
     };
 
     new_doc.objects.insert(new_id, cloned);
@@ -81,6 +121,159 @@ pub(crate) fn clone_object(
     }
 }
 
+/// How many of the objects cloned out of `doc` did not exist there and were cloned as null.
+pub(crate) fn missing_object_count(doc: &Document, id_map: &BTreeMap<ObjectId, ObjectId>) -> usize {
+    id_map
+        .keys()
+        .filter(|&&id| doc.get_object(id).is_err())
+        .count()
+}
+
+/// Parses a PDF with lopdf. Its parser is handed whatever the user opens, so a panic on a
+/// malformed file is caught and reported like any other load error.
+pub(crate) fn load_document(file_path: &str) -> Result<Document, String> {
+    match panic::catch_unwind(|| Document::load(file_path)) {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(_) => Err("the file could not be parsed".to_string()),
+    }
+}
+
+/// A parsed and decrypted source of a merge, with its page numbers resolved.
+pub(crate) struct SourceDocument {
+    pub doc: Document,
+    pub pages: BTreeMap<u32, ObjectId>,
+}
+
+pub(crate) fn load_source_document(
+    file_path: &str,
+    password: Option<&str>,
+) -> Result<SourceDocument, CommandError> {
+    // Files pdfium can render may still be too damaged for lopdf, which gets a repaired copy.
+    // That includes files without a catalog, where nothing leads to the pages.
+    let mut doc = match load_document(file_path) {
+        Ok(doc) if doc.catalog().is_ok() => doc,
+        result => {
+            let cause = match result {
+                Ok(_) => "the file has no catalog".to_string(),
+                Err(cause) => cause,
+            };
+            let repaired = fs::read(file_path)
+                .ok()
+                .and_then(|buffer| repair_document(&buffer).ok());
+            match repaired {
+                // What was lost is reported when the file is opened, which repairs it the same way
+                Some((doc, _)) => doc,
+                None => return Err(ErrorCode::PdfPageLoadError.because(cause)),
+            }
+        }
+    };
+
+    // Decrypt if encrypted; owner-password-only files open with an empty user password
+    if doc.is_encrypted() {
+        doc.decrypt(password.unwrap_or(""))
+            .map_err(|e| ErrorCode::PdfPageDecryptError.because(e))?;
+        doc.trailer.remove(b"Encrypt");
+    }
+
+    let pages = doc.get_pages();
+    Ok(SourceDocument { doc, pages })
+}
+
+pub(crate) fn ensure_document_id(doc: &mut Document) {
+    if doc.trailer.get(b"ID").is_err() {
+        // Generate a random 16-byte ID (twice, as per PDF spec)
+        let id = Uuid::new_v4().as_bytes().to_vec();
+        let id_obj = Object::Array(vec![
+            Object::String(id.clone(), lopdf::StringFormat::Hexadecimal),
+            Object::String(id, lopdf::StringFormat::Hexadecimal),
+        ]);
+        doc.trailer.set("ID", id_obj);
+    }
+}
+
+pub(crate) fn permissions_from_data(data: Option<PermissionsData>) -> Permissions {
+    let Some(data) = data else {
+        return Permissions::default();
+    };
+
+    let mut permissions = Permissions::empty();
+    permissions.set(Permissions::PRINTABLE, data.print);
+    permissions.set(Permissions::PRINTABLE_IN_HIGH_QUALITY, data.print && data.print_high_quality);
+    permissions.set(Permissions::MODIFIABLE, data.modify);
+    permissions.set(Permissions::COPYABLE, data.copy);
+    permissions.set(Permissions::ANNOTABLE, data.annotate);
+    permissions.set(Permissions::FILLABLE, data.fill_forms);
+    permissions.set(Permissions::ASSEMBLABLE, data.assemble);
+    // Must always be set for backward compatibility (deprecated in PDF 2.0)
+    permissions.insert(Permissions::COPYABLE_FOR_ACCESSIBILITY);
+    permissions
+}
+
+pub(crate) fn encrypt_document(
+    doc: &mut Document,
+    user_password: &str,
+    owner_password: &str,
+    encryption_level: EncryptionLevel,
+    permissions: Permissions,
+) -> Result<(), CommandError> {
+    let mut crypt_filters: BTreeMap<Vec<u8>, Arc<dyn CryptFilter>> = BTreeMap::new();
+    let stream_filter = b"Default".to_vec();
+    let string_filter = b"Default".to_vec();
+
+    let state = if encryption_level == EncryptionLevel::Aes128 {
+        // Use V4 encryption with AES-128 for PDF version 1.5
+        crypt_filters.insert(b"Default".to_vec(), Arc::new(Aes128CryptFilter));
+        let version = EncryptionVersion::V4 {
+            document: doc,
+            encrypt_metadata: true,
+            crypt_filters,
+            stream_filter,
+            string_filter,
+            owner_password,
+            user_password,
+            permissions,
+        };
+
+        EncryptionState::try_from(version)
+            .map_err(|e| ErrorCode::PdfEncryptError.because(format!("AES-128: {e:?}")))?
+    } else {
+        // For AES-256, generate a 32-byte key and use V5 encryption (which requires PDF version 1.7)
+        let mut file_encryption_key = [0u8; 32];
+        rand::rng().fill(&mut file_encryption_key);
+
+        crypt_filters.insert(b"Default".to_vec(), Arc::new(Aes256CryptFilter));
+
+        let version = EncryptionVersion::V5 {
+            encrypt_metadata: true,
+            crypt_filters,
+            file_encryption_key: &file_encryption_key,
+            stream_filter,
+            string_filter,
+            owner_password,
+            user_password,
+            permissions,
+        };
+
+        EncryptionState::try_from(version)
+            .map_err(|e| ErrorCode::PdfEncryptError.because(format!("AES-256: {e:?}")))?
+    };
+
+    doc.encrypt(&state)
+        .map_err(|e| ErrorCode::PdfEncryptError.because(format!("{e:?}")))?;
+
+    Ok(())
+}
+
+/// An owner password for files encrypted without one. Nobody knows it, so the permissions stay in
+/// force for everyone who opens the file.
+pub(crate) fn random_owner_password() -> String {
+    rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
 pub(crate) fn get_unique_output_path(output_path: std::path::PathBuf) -> std::path::PathBuf {
     let parent = output_path
         .parent()
@@ -105,8 +298,11 @@ pub(crate) fn get_unique_output_path(output_path: std::path::PathBuf) -> std::pa
     candidate
 }
 
-pub(crate) fn init_pdfium(app: &AppHandle) -> Result<Pdfium, String> {
-    let resource_path = app.path().resource_dir().unwrap();
+pub(crate) fn init_pdfium(app: &AppHandle) -> Result<Pdfium, CommandError> {
+    let resource_path = app
+        .path()
+        .resource_dir()
+        .map_err(|e| ErrorCode::PdfiumInitError.because(e))?;
 
     // Determine platform-specific library path
     #[cfg(target_os = "windows")]
@@ -121,27 +317,115 @@ pub(crate) fn init_pdfium(app: &AppHandle) -> Result<Pdfium, String> {
     // Initialize PDFium with our bundled library
     let pdfium = Pdfium::new(
         Pdfium::bind_to_library(lib_path.to_string_lossy().to_string())
-            .map_err(|e| format!("Failed to bind to PDFium library: {}", e))?,
+            .map_err(|e| ErrorCode::PdfiumInitError.because(e).with_file(&lib_path))?,
     );
 
     Ok(pdfium)
 }
 
+fn password_error_code(error: &PdfiumError, password_given: bool) -> Option<ErrorCode> {
+    match error {
+        PdfiumError::PdfiumLibraryInternalError(PdfiumInternalError::PasswordError) => {
+            if password_given {
+                Some(ErrorCode::PdfPasswordIncorrect)
+            } else {
+                Some(ErrorCode::PdfPasswordRequired)
+            }
+        }
+        PdfiumError::PdfiumLibraryInternalError(PdfiumInternalError::SecurityError) => {
+            Some(ErrorCode::PdfSecurityUnsupported)
+        }
+        _ => None,
+    }
+}
+
+/// The error for a document pdfium could not open, telling password problems apart.
+pub(crate) fn open_error(error: PdfiumError, password_given: bool) -> CommandError {
+    match password_error_code(&error, password_given) {
+        Some(code) => CommandError::new(code),
+        None => ErrorCode::PdfLoadError.because(error),
+    }
+}
+
+pub(crate) fn get_document_permissions(document: &PdfDocument) -> DocumentPermissions {
+    // pdfium-render only interprets revisions 2 to 4 and fails for AES-256 files, so the raw
+    // values are read instead. With the owner password pdfium reports every permission as set.
+    let bindings = document.bindings();
+    let handle = bindings.get_handle_from_document(document);
+    permissions_from_bits(
+        bindings.FPDF_GetSecurityHandlerRevision(handle),
+        bindings.FPDF_GetDocPermissions(handle) as u32,
+    )
+}
+
+/// Interprets the `/P` bits of the standard security handler (PDF 32000-1, Table 22), for
+/// handler revision `revision`, which pdfium reports as -1 for unencrypted files.
+fn permissions_from_bits(revision: i32, bits: u32) -> DocumentPermissions {
+    if revision < 0 {
+        return DocumentPermissions {
+            encrypted: false,
+            can_print: true,
+            can_modify: true,
+            can_copy: true,
+            can_annotate: true,
+            can_fill_forms: true,
+            can_assemble: true,
+        };
+    }
+
+    // Bits are numbered from 1
+    let bit = |n: u32| bits & (1 << (n - 1)) != 0;
+    let can_modify = bit(4);
+    let can_annotate = bit(6);
+    DocumentPermissions {
+        encrypted: true,
+        can_print: bit(3),
+        can_modify,
+        can_copy: bit(5),
+        can_annotate,
+        // Revision 2 has no bits of its own for these
+        can_fill_forms: can_annotate || (revision > 2 && bit(9)),
+        can_assemble: can_modify || (revision > 2 && bit(11)),
+    }
+}
+
+/// Re-exporting a file copies its pages into a new document, which the author may have forbidden.
+/// Opening the file with the owner password lifts all restrictions.
+pub(crate) fn check_export_permissions(
+    pdfium: &PdfiumState,
+    file_path: &str,
+    password: Option<&str>,
+) -> Result<(), CommandError> {
+    let document = pdfium
+        .open(file_path, password)
+        .map_err(|e| open_error(e, password.is_some()))?;
+
+    let permissions = get_document_permissions(&document);
+    if !permissions.can_copy || !permissions.can_assemble {
+        return Err(CommandError::new(ErrorCode::PdfPermissionDenied));
+    }
+
+    Ok(())
+}
+
 pub(crate) fn convert_text_to_pdf(
     app: &AppHandle,
     text: &str,
     output_path: &Path,
-) -> Result<(), String> {
+) -> Result<(), CommandError> {
     let lines: Vec<&str> = text.lines().collect();
 
     // Load the font from resources
-    let resource_path = app.path().resource_dir().unwrap();
+    let resource_path = app
+        .path()
+        .resource_dir()
+        .map_err(|e| ErrorCode::ResourceMissing.because(e))?;
     let assets_path = resource_path.join("assets");
     let fonts_path = assets_path.join("fonts");
 
     // Create a new document with DejaVuSans font
     let font_family = fonts::from_files(fonts_path, "DejaVuSans", None)
-        .map_err(|e| format!("Failed to load font family: {}", e))?;
+        .map_err(|e| ErrorCode::PdfConvertError.because(e))?;
     let mut doc = GenDocument::new(font_family);
 
     // Set up page decorator with margins
@@ -156,17 +440,64 @@ pub(crate) fn convert_text_to_pdf(
 
     // Render the document
     doc.render_to_file(output_path)
-        .map_err(|e| format!("Failed to render PDF: {}", e))?;
+        .map_err(|e| ErrorCode::PdfConvertError.because(e))?;
 
     Ok(())
 }
 
+/// Renders `text` onto a `width` x `height` page with the bundled font, returning the PDF.
+pub(crate) fn render_separator_page(
+    app: &AppHandle,
+    text: &str,
+    width: f32,
+    height: f32,
+) -> Result<Vec<u8>, CommandError> {
+    let fonts_path = app
+        .path()
+        .resource_dir()
+        .map_err(|e| ErrorCode::ResourceMissing.because(e))?
+        .join("assets")
+        .join("fonts");
+    let font_family = fonts::from_files(fonts_path, "DejaVuSans", None)
+        .map_err(|e| ErrorCode::PdfGeneratePageError.because(e))?;
+
+    let mut doc = GenDocument::new(font_family);
+    // genpdf measures in millimeters
+    let to_mm = |points: f32| f64::from(points) * 25.4 / 72.0;
+    doc.set_paper_size(genpdf::Size::new(to_mm(width), to_mm(height)));
+
+    let mut decorator = SimplePageDecorator::new();
+    decorator.set_margins(20); // 20mm margins
+    doc.set_page_decorator(decorator);
+
+    let mut lines = text.lines();
+    if let Some(heading) = lines.next() {
+        // Leave about a third of the page above the heading; a line of body text is about 5mm
+        doc.push(elements::Break::new(to_mm(height) / 3.0 / 5.0));
+        doc.push(
+            elements::Paragraph::new(heading)
+                .aligned(Alignment::Center)
+                .styled(style::Style::new().bold().with_font_size(24)),
+        );
+        doc.push(elements::Break::new(1.5));
+    }
+    for line in lines {
+        doc.push(elements::Paragraph::new(line).aligned(Alignment::Center));
+    }
+
+    let mut buffer = Vec::new();
+    doc.render(&mut buffer)
+        .map_err(|e| ErrorCode::PdfGeneratePageError.because(e))?;
+
+    Ok(buffer)
+}
+
 pub(crate) fn convert_image_to_pdf(
     _app: &AppHandle,
     image_path: &str,
     output_path: &Path,
-) -> Result<(), String> {
-    let img = image::open(image_path).map_err(|e| format!("Failed to open image: {}", e))?;
+) -> Result<(), CommandError> {
+    let img = image::open(image_path).map_err(|e| ErrorCode::PdfConvertError.because(e))?;
     let (width, height) = img.dimensions();
 
     // Convert image to RGB (removes alpha channel if present) and encode as JPEG
@@ -174,7 +505,7 @@ pub(crate) fn convert_image_to_pdf(
     let mut img_buf = Vec::new();
     rgb_img
         .write_to(&mut Cursor::new(&mut img_buf), image::ImageFormat::Jpeg)
-        .map_err(|e| format!("Failed to encode image as JPEG: {}", e))?;
+        .map_err(|e| ErrorCode::PdfConvertError.because(e))?;
 
     let mut doc = Document::with_version("1.5");
 
@@ -234,56 +565,117 @@ pub(crate) fn convert_image_to_pdf(
 
     // Save the new PDF
     doc.save(output_path)
-        .map_err(|e| format!("Failed to save PDF: {}", e))?;
+        .map_err(|e| ErrorCode::PdfConvertError.because(e))?;
     Ok(())
 }
 
-pub(crate) fn get_temp_pdf_path(document_id: &str) -> std::path::PathBuf {
-    let temp_dir = std::env::temp_dir();
-    temp_dir.join(format!("{}.pdf", document_id))
+pub(crate) fn get_downloads_dir(app: &AppHandle) -> Result<PathBuf, CommandError> {
+    app.path()
+        .download_dir()
+        .map_err(|e| ErrorCode::PdfOutputPathError.because(e))
 }
 
-pub(crate) fn get_downloads_dir(app: &AppHandle) -> Result<PathBuf, String> {
-    Ok(app
-        .path()
-        .download_dir()
-        .map_err(|_| "Could not get downloads directory")?)
+/// Where an export named `file_name` is written, following the destination and its overwrite
+/// policy. The name is sanitized, and a save dialog path keeps its folder but not an unsafe name.
+pub(crate) fn get_output_path(
+    app: &AppHandle,
+    file_name: &str,
+    destination: &OutputDestination,
+) -> Result<PathBuf, CommandError> {
+    let output_path = match (&destination.file_path, &destination.directory) {
+        (Some(file_path), _) => {
+            let file_path = Path::new(file_path);
+            let directory = file_path.parent().unwrap_or_else(|| Path::new(""));
+            let chosen_name = file_path
+                .file_name()
+                .map(|name| name.to_string_lossy())
+                .unwrap_or_default();
+            directory.join(sanitize_file_name(&chosen_name))
+        }
+        (None, Some(directory)) => Path::new(directory).join(sanitize_file_name(file_name)),
+        (None, None) => get_downloads_dir(app)?.join(sanitize_file_name(file_name)),
+    };
+
+    // Relative paths would resolve against wherever the app happens to run from
+    let directory = output_path.parent().unwrap_or_else(|| Path::new(""));
+    if !directory.is_absolute() || !directory.is_dir() {
+        return Err(ErrorCode::PdfOutputPathError
+            .because("the folder does not exist")
+            .with_file(directory));
+    }
+
+    match destination.overwrite {
+        OverwritePolicy::Unique => Ok(get_unique_output_path(output_path)),
+        OverwritePolicy::Overwrite => Ok(output_path),
+        OverwritePolicy::Fail if output_path.exists() => {
+            Err(CommandError::new(ErrorCode::PdfOutputExists).with_file(&output_path))
+        }
+        OverwritePolicy::Fail => Ok(output_path),
+    }
 }
 
-pub(crate) fn get_output_path(app: &AppHandle, file_name: &str) -> Result<PathBuf, String> {
-    let downloads_dir = get_downloads_dir(app)?;
+/// Makes a user-supplied name safe to use as a single file name on every platform, ending in
+/// `.pdf`. Path separators, characters Windows does not allow and control characters become
+/// `_`, and names Windows reserves for devices get a `_` in front.
+pub(crate) fn sanitize_file_name(file_name: &str) -> String {
+    let name: String = file_name
+        .chars()
+        .map(|c| {
+            if ILLEGAL_FILE_NAME_CHARS.contains(&c) || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
 
-    let output_file_name = if file_name.to_lowercase().ends_with(".pdf") {
-        file_name.to_string()
-    } else {
-        // Remove any existing extension and add .pdf
-        let stem = Path::new(file_name)
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or(file_name);
-        format!("{}.pdf", stem)
+    // Only a `.pdf` extension is dropped, so dots inside names like `Report v1.2` stay
+    let stem = match name.get(name.len().saturating_sub(4)..) {
+        Some(extension) if extension.eq_ignore_ascii_case(".pdf") => &name[..name.len() - 4],
+        _ => &name,
     };
+    // Windows drops trailing dots and spaces, which also rules out `.` and `..`
+    let stem: String = stem
+        .trim()
+        .trim_end_matches(['.', ' '])
+        .chars()
+        .take(MAX_FILE_STEM_CHARS)
+        .collect();
+    if stem.is_empty() {
+        return "output.pdf".to_string();
+    }
 
-    Ok(get_unique_output_path(downloads_dir.join(output_file_name)))
+    let device = stem.split('.').next().unwrap_or_default().to_uppercase();
+    if RESERVED_FILE_NAMES.contains(&device.as_str()) {
+        format!("_{}.pdf", stem)
+    } else {
+        format!("{}.pdf", stem)
+    }
 }
 
-pub(crate) fn get_file_ext(file_path: &str) -> Result<String, String> {
+pub(crate) fn get_file_ext(file_path: &str) -> Result<String, CommandError> {
     Path::new(file_path)
         .extension()
         .and_then(|ext| ext.to_str())
         .map(|ext| ext.to_lowercase())
-        .ok_or_else(|| format!("Could not get file extension for {}", file_path))
+        .ok_or_else(|| CommandError::new(ErrorCode::FileTypeUnsupported).with_file(file_path))
 }
 
-fn gen_render_config(width: f32, height: f32, quality: ImageQuality) -> PdfRenderConfig {
+pub(crate) fn gen_render_size(width: f32, height: f32, quality: ImageQuality) -> (i32, i32) {
     let scale = match quality {
         ImageQuality::Low => 1.0,
         ImageQuality::High => 3.0,
     };
 
+    ((width * scale) as i32, (height * scale) as i32)
+}
+
+fn gen_render_config(width: f32, height: f32, quality: ImageQuality) -> PdfRenderConfig {
+    let (target_width, target_height) = gen_render_size(width, height, quality);
+
     PdfRenderConfig::new()
-        .set_target_width((width * scale) as i32)
-        .set_target_height((height * scale) as i32)
+        .set_target_width(target_width)
+        .set_target_height(target_height)
 }
 
 pub(crate) fn gen_image_bitmap<'a>(
@@ -291,32 +683,129 @@ pub(crate) fn gen_image_bitmap<'a>(
     width: f32,
     height: f32,
     quality: ImageQuality
-) -> Result<PdfBitmap<'a>, String> {
+) -> Result<PdfBitmap<'a>, CommandError> {
     let render_config = gen_render_config(width, height, quality);
 
     let bitmap = page
         .render_with_config(&render_config)
-        .map_err(|e| format!("Failed to render PDF page: {}", e))?;
+        .map_err(|e| ErrorCode::PdfRenderError.because(e))?;
 
     Ok(bitmap)
 }
 
-pub(crate) fn gen_data_url_from_buffer(buffer: &[u8]) -> String {
-    let base64_data = base64::engine::general_purpose::STANDARD.encode(&buffer);
-    format!("data:image/webp;base64,{}", base64_data)
+/// Clamps `region` to the page and lowers `scale` until the tile fits the pixel budget.
+/// Returns `None` if nothing of the region lies on the page.
+pub(crate) fn fit_region(
+    page_width: f32,
+    page_height: f32,
+    region: RegionRect,
+    scale: f32,
+) -> Option<(RegionRect, f32)> {
+    let left = region.x.max(0.0);
+    let top = region.y.max(0.0);
+    let right = (region.x + region.width).min(page_width);
+    let bottom = (region.y + region.height).min(page_height);
+    if right <= left || bottom <= top || !scale.is_finite() || scale <= 0.0 {
+        return None;
+    }
+
+    let region = RegionRect {
+        x: left,
+        y: top,
+        width: right - left,
+        height: bottom - top,
+    };
+    let max_scale = (MAX_REGION_PIXELS / (region.width * region.height)).sqrt();
+
+    Some((region, scale.min(max_scale)))
 }
 
-pub(crate) fn gen_image_data_url(bitmap: &PdfBitmap) -> Result<String, String> {
-    let dynamic_image = DynamicImage::from(bitmap.as_image());
-    let mut buffer = Vec::new();
+pub(crate) fn region_pixel_size(region: RegionRect, scale: f32) -> (u32, u32) {
+    (
+        ((region.width * scale).round() as u32).max(1),
+        ((region.height * scale).round() as u32).max(1),
+    )
+}
 
-    dynamic_image
-        .write_to(&mut Cursor::new(&mut buffer), image::ImageFormat::WebP)
-        .map_err(|e| format!("Failed to encode WebP: {}", e))?;
+/// Renders only `region` of the page, at `scale` pixels per point.
+pub(crate) fn gen_region_bitmap<'a>(
+    page: &'a PdfPage,
+    region: RegionRect,
+    scale: f32,
+) -> Result<PdfBitmap<'a>, CommandError> {
+    let (width, height) = region_pixel_size(region, scale);
+
+    // The bitmap is the size of the tile; the page is moved so the region lands on it
+    let render_config = PdfRenderConfig::new()
+        .set_fixed_size(width as i32, height as i32)
+        .scale_page_by_factor(scale)
+        .translate(PdfPoints::new(-region.x), PdfPoints::new(-region.y))
+        .map_err(|e| ErrorCode::PdfRenderError.because(e))?;
 
-    let data_url = gen_data_url_from_buffer(&buffer);
+    let bitmap = page
+        .render_with_config(&render_config)
+        .map_err(|e| ErrorCode::PdfRenderError.because(e))?;
+
+    Ok(bitmap)
+}
+
+/// Thumbnails are cached per content hash; pass no `file_hash` to bypass the cache,
+/// e.g. for password-protected documents.
+pub(crate) fn thumbnail_cache_key(
+    file_hash: Option<&str>,
+    page_index: usize,
+    encoding: ImageEncoding,
+) -> Option<ThumbnailCacheKey<'_>> {
+    file_hash.map(|file_hash| ThumbnailCacheKey {
+        file_hash,
+        page_index,
+        height: THUMBNAIL_HEIGHT as u32,
+        encoding,
+    })
+}
+
+pub(crate) fn render_thumbnail_image(page: &PdfPage) -> Result<DynamicImage, CommandError> {
+    let (page_width, page_height) = page_display_size(page);
+    let aspect_ratio = page_width / page_height;
+    let target_width = THUMBNAIL_HEIGHT * aspect_ratio;
+
+    let bitmap = gen_image_bitmap(page, target_width, THUMBNAIL_HEIGHT, ImageQuality::Low)?;
 
-    Ok(data_url)
+    Ok(DynamicImage::from(bitmap.as_image()))
+}
+
+/// Encodes a rendered page. The bytes are labelled with `encoding.format.mime_type()` when served.
+pub(crate) fn encode_image(
+    image: &DynamicImage,
+    encoding: ImageEncoding,
+) -> Result<Vec<u8>, CommandError> {
+    let quality = encoding.quality.clamp(1, 100);
+    let mut buffer = Vec::new();
+
+    let result = match encoding.format {
+        OutputFormat::Png => image.write_to(&mut Cursor::new(&mut buffer), image::ImageFormat::Png),
+        // The image crate only writes lossless WebP, so both variants go through libwebp
+        OutputFormat::WebpLossless | OutputFormat::WebpLossy => {
+            let rgba = image.to_rgba8();
+            let encoded = webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height())
+                .encode_simple(encoding.format == OutputFormat::WebpLossless, quality as f32)
+                .map_err(|e| ErrorCode::ImageEncodeError.because(format!("WebP: {:?}", e)))?;
+            buffer.extend_from_slice(&encoded);
+            Ok(())
+        }
+        // JPEG has no alpha channel
+        OutputFormat::Jpeg => DynamicImage::from(image.to_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut buffer, quality)),
+        OutputFormat::Avif => {
+            // Speed 8 of 10 keeps encoding fast enough for scrolling through a document
+            image.write_with_encoder(AvifEncoder::new_with_speed_quality(&mut buffer, 8, quality))
+        }
+    };
+    result.map_err(|e| {
+        ErrorCode::ImageEncodeError.because(format!("{:?}: {}", encoding.format, e))
+    })?;
+
+    Ok(buffer)
 }
 
 pub(crate) fn file_name_from_path(file_path: &str) -> String {
@@ -331,4 +820,160 @@ pub(crate) fn file_name_from_path(file_path: &str) -> String {
             }
         })
         .to_string()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::upload::fixtures::{fixture_path, sample_document};
+
+    fn fixture(name: &str) -> String {
+        fixture_path(name).to_string_lossy().to_string()
+    }
+
+    fn reference(dict: &Dictionary, key: &[u8]) -> ObjectId {
+        dict.get(key).and_then(Object::as_reference).unwrap()
+    }
+
+    // The revision and `/P` value of the standard security handler of an encrypted document
+    fn security_handler(doc: &Document) -> (i32, u32) {
+        let encrypt = doc
+            .trailer
+            .get(b"Encrypt")
+            .and_then(Object::as_reference)
+            .and_then(|id| doc.get_dictionary(id))
+            .expect("encryption dictionary");
+        let revision = encrypt.get(b"R").and_then(Object::as_i64).unwrap();
+        let bits = encrypt.get(b"P").and_then(Object::as_i64).unwrap();
+        (revision as i32, bits as u32)
+    }
+
+    fn print_only() -> Permissions {
+        permissions_from_data(Some(PermissionsData {
+            print: true,
+            print_high_quality: true,
+            modify: false,
+            copy: false,
+            annotate: false,
+            fill_forms: false,
+            assemble: false,
+        }))
+    }
+
+    #[test]
+    fn unencrypted_files_allow_everything() {
+        let permissions = permissions_from_bits(-1, 0);
+        assert!(!permissions.encrypted);
+        assert!(permissions.can_copy && permissions.can_assemble && permissions.can_print);
+    }
+
+    #[test]
+    fn aes_256_restrictions_are_read() {
+        let mut doc = sample_document(1);
+        ensure_document_id(&mut doc);
+        encrypt_document(&mut doc, "", "owner", EncryptionLevel::Aes256, print_only()).unwrap();
+        let (revision, bits) = security_handler(&doc);
+        assert_eq!(revision, 6);
+
+        let permissions = permissions_from_bits(revision, bits);
+        assert!(permissions.encrypted);
+        assert!(permissions.can_print);
+        assert!(!permissions.can_copy);
+        assert!(!permissions.can_assemble);
+    }
+
+    #[test]
+    fn aes_128_restrictions_are_read() {
+        let mut doc = sample_document(1);
+        ensure_document_id(&mut doc);
+        encrypt_document(&mut doc, "", "owner", EncryptionLevel::Aes128, print_only()).unwrap();
+        let (revision, bits) = security_handler(&doc);
+        assert_eq!(revision, 4);
+
+        let permissions = permissions_from_bits(revision, bits);
+        assert!(permissions.can_print);
+        assert!(!permissions.can_copy && !permissions.can_modify);
+    }
+
+    #[test]
+    fn dotted_names_keep_their_dots() {
+        assert_eq!(sanitize_file_name("Report v1.2"), "Report v1.2.pdf");
+        assert_eq!(sanitize_file_name("Report v1.2.PDF"), "Report v1.2.pdf");
+        assert_eq!(sanitize_file_name("archive.tar"), "archive.tar.pdf");
+        assert_eq!(sanitize_file_name("notes..."), "notes.pdf");
+    }
+
+    #[test]
+    fn illegal_characters_are_replaced() {
+        assert_eq!(sanitize_file_name("a/b\\c:d*e?f\"g"), "a_b_c_d_e_f_g.pdf");
+        assert_eq!(sanitize_file_name("<h>i|j"), "_h_i_j.pdf");
+        assert_eq!(sanitize_file_name("tab\there.pdf"), "tab_here.pdf");
+        assert_eq!(sanitize_file_name("../.."), ".._.pdf");
+        assert_eq!(sanitize_file_name(" . "), "output.pdf");
+        assert_eq!(sanitize_file_name(".pdf"), "output.pdf");
+    }
+
+    #[test]
+    fn reserved_names_are_prefixed() {
+        assert_eq!(sanitize_file_name("CON"), "_CON.pdf");
+        assert_eq!(sanitize_file_name("con.pdf"), "_con.pdf");
+        assert_eq!(sanitize_file_name("Com1.backup"), "_Com1.backup.pdf");
+        assert_eq!(sanitize_file_name("CONTRACT"), "CONTRACT.pdf");
+    }
+
+    #[test]
+    fn long_names_are_shortened() {
+        let name = sanitize_file_name(&"é".repeat(300));
+        assert_eq!(name.chars().count(), MAX_FILE_STEM_CHARS + 4);
+    }
+
+    #[test]
+    fn owner_passwords_are_random() {
+        let password = random_owner_password();
+        assert_eq!(password.len(), 32);
+        assert!(password.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(password, random_owner_password());
+    }
+
+    #[test]
+    fn damaged_files_fail_to_load_without_panicking() {
+        assert!(load_document(&fixture("truncated.pdf")).is_err());
+        assert!(load_document(&fixture("bad_xref.pdf")).is_err());
+        assert!(load_document(&fixture("does_not_exist.pdf")).is_err());
+    }
+
+    #[test]
+    fn damaged_sources_are_repaired_for_merging() {
+        for name in ["truncated.pdf", "bad_xref.pdf", "missing_root.pdf"] {
+            let source = load_source_document(&fixture(name), None).unwrap();
+            assert_eq!(source.pages.len(), 2, "{}", name);
+        }
+    }
+
+    #[test]
+    fn unreadable_source_is_a_page_load_error() {
+        let result = load_source_document(&fixture("does_not_exist.pdf"), None);
+        assert_eq!(result.err().unwrap().code, ErrorCode::PdfPageLoadError);
+    }
+
+    #[test]
+    fn reference_loops_are_cloned_once() {
+        let doc = load_document(&fixture("reference_loop.pdf")).unwrap();
+        let mut new_doc = Document::with_version("1.7");
+        let mut id_map = BTreeMap::new();
+        let page_id = clone_with_dependencies(&doc, doc.get_pages()[&1], &mut new_doc, &mut id_map);
+
+        let dict = |id: ObjectId| new_doc.get_dictionary(id).unwrap();
+        // Objects 8 and 9 refer to each other
+        let first = reference(dict(page_id), b"PieceInfo");
+        let second = reference(dict(first), b"Next");
+        assert_ne!(first, second);
+        assert_eq!(reference(dict(second), b"Next"), first);
+        assert_eq!(id_map.len(), new_doc.objects.len());
+
+        // Object 20 does not exist
+        let missing = reference(dict(first), b"Missing");
+        assert!(matches!(new_doc.objects.get(&missing), Some(Object::Null)));
+        assert_eq!(missing_object_count(&doc, &id_map), 1);
+    }
+}
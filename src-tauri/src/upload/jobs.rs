@@ -0,0 +1,96 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+use uuid::Uuid;
+
+use crate::upload::structs::JobProgress;
+use crate::upload::sync::lock;
+
+#[derive(Default)]
+struct JobState {
+    cancelled: AtomicBool,
+    done: AtomicUsize,
+    total: AtomicUsize,
+}
+
+/// A running job as seen by the code doing the work.
+#[derive(Clone)]
+pub(crate) struct JobHandle {
+    pub id: String,
+    state: Arc<JobState>,
+}
+
+impl JobHandle {
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_total(&self, total: usize) {
+        self.state.total.store(total, Ordering::Relaxed);
+    }
+
+    /// Marks one more unit of work (usually a page) as done.
+    pub(crate) fn advance(&self) -> JobProgress {
+        let done = self.state.done.fetch_add(1, Ordering::Relaxed) + 1;
+        JobProgress {
+            job_id: self.id.clone(),
+            done,
+            total: self.state.total.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Registry of long-running operations (merges, thumbnail renders) that can be cancelled.
+#[derive(Default)]
+pub struct JobManager {
+    jobs: Mutex<HashMap<String, Arc<JobState>>>,
+}
+
+impl JobManager {
+    /// Starts a job under the given id, or a fresh one. A job still running under the same id is cancelled.
+    pub(crate) fn start(&self, job_id: Option<String>) -> JobHandle {
+        let id = job_id.unwrap_or_else(|| Uuid::new_v4().to_string());
+        let state = Arc::new(JobState::default());
+
+        let mut jobs = lock(&self.jobs);
+        if let Some(previous) = jobs.insert(id.clone(), state.clone()) {
+            previous.cancelled.store(true, Ordering::Relaxed);
+        }
+
+        JobHandle { id, state }
+    }
+
+    /// Returns `false` if no job with this id is running.
+    pub(crate) fn cancel(&self, job_id: &str) -> bool {
+        match lock(&self.jobs).get(job_id) {
+            Some(state) => {
+                state.cancelled.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn progress(&self, job_id: &str) -> Option<JobProgress> {
+        lock(&self.jobs).get(job_id).map(|state| JobProgress {
+            job_id: job_id.to_string(),
+            done: state.done.load(Ordering::Relaxed),
+            total: state.total.load(Ordering::Relaxed),
+        })
+    }
+
+    /// Removes the job, unless a newer job has taken over its id in the meantime.
+    pub(crate) fn finish(&self, handle: &JobHandle) {
+        let mut jobs = lock(&self.jobs);
+        if jobs
+            .get(&handle.id)
+            .is_some_and(|current| Arc::ptr_eq(current, &handle.state))
+        {
+            jobs.remove(&handle.id);
+        }
+    }
+}
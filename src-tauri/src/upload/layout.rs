@@ -0,0 +1,569 @@
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+use std::collections::BTreeMap;
+
+use crate::upload::boxes::{box_object, crop_box, page_rotation};
+use crate::upload::enums::PageLayout;
+use crate::upload::error::ErrorCode;
+
+// [a b c d e f] as in the PDF `cm` operator: x' = a*x + c*y + e, y' = b*x + d*y + f
+pub(crate) type Matrix = [f32; 6];
+
+// Page keys that describe the old geometry, and no longer apply once the page is laid out anew
+const GEOMETRY_KEYS: [&[u8]; 6] = [
+    b"MediaBox",
+    b"CropBox",
+    b"BleedBox",
+    b"TrimBox",
+    b"ArtBox",
+    b"Rotate",
+];
+
+/// The content of a page as a Form XObject.
+pub(crate) struct PageForm {
+    pub xobject_id: ObjectId,
+    // Size of the page as displayed, after cropping and rotation
+    pub width: f32,
+    pub height: f32,
+    // Maps the form's coordinates onto the displayed page, its bottom left corner at the origin
+    pub to_display: Matrix,
+}
+
+/// `first` is applied first.
+pub(crate) fn multiply(first: Matrix, then: Matrix) -> Matrix {
+    let [a1, b1, c1, d1, e1, f1] = first;
+    let [a2, b2, c2, d2, e2, f2] = then;
+    [
+        a1 * a2 + b1 * c2,
+        a1 * b2 + b1 * d2,
+        c1 * a2 + d1 * c2,
+        c1 * b2 + d1 * d2,
+        e1 * a2 + f1 * c2 + e2,
+        e1 * b2 + f1 * d2 + f2,
+    ]
+}
+
+pub(crate) fn scale_and_translate(scale: f32, x: f32, y: f32) -> Matrix {
+    [scale, 0.0, 0.0, scale, x, y]
+}
+
+fn apply(matrix: Matrix, x: f32, y: f32) -> (f32, f32) {
+    let [a, b, c, d, e, f] = matrix;
+    (a * x + c * y + e, b * x + d * y + f)
+}
+
+/// Scale and offset that fit a `width` x `height` page centered into the given cell.
+pub(crate) fn fit_into(
+    width: f32,
+    height: f32,
+    cell_x: f32,
+    cell_y: f32,
+    cell_width: f32,
+    cell_height: f32,
+) -> Matrix {
+    let scale = (cell_width / width).min(cell_height / height);
+    scale_and_translate(
+        scale,
+        cell_x + (cell_width - width * scale) / 2.0,
+        cell_y + (cell_height - height * scale) / 2.0,
+    )
+}
+
+/// Turns the content of the page into a Form XObject, so it can be drawn anywhere at any scale
+/// while text and vector graphics stay vector.
+pub(crate) fn page_to_form(
+    doc: &mut Document,
+    page_id: ObjectId,
+) -> Result<PageForm, ErrorCode> {
+    let visible = crop_box(doc, page_id);
+    let rotation = page_rotation(doc, page_id);
+    let page = doc
+        .get_dictionary(page_id)
+        .map_err(|_| ErrorCode::PdfPageNotFound)?;
+
+    let mut content = Vec::new();
+    for stream_id in doc.get_page_contents(page_id) {
+        let stream = doc
+            .get_object(stream_id)
+            .and_then(Object::as_stream)
+            .map_err(|_| ErrorCode::PdfPageLayoutError)?;
+        content.extend(stream.get_plain_content().map_err(|_| ErrorCode::PdfPageLayoutError)?);
+        // Keep operators of consecutive streams apart
+        content.push(b'\n');
+    }
+
+    let [left, bottom, right, top] = visible;
+    let mut dict = Dictionary::new();
+    dict.set("Type", Object::Name(b"XObject".to_vec()));
+    dict.set("Subtype", Object::Name(b"Form".to_vec()));
+    dict.set("BBox", box_object(visible));
+    if let Ok(resources) = page.get(b"Resources") {
+        dict.set("Resources", resources.clone());
+    }
+    // Transparency groups affect how the content is composited
+    if let Ok(group) = page.get(b"Group") {
+        dict.set("Group", group.clone());
+    }
+
+    let mut stream = Stream::new(dict, content);
+    // Not compressing only costs file size
+    let _ = stream.compress();
+    let xobject_id = doc.add_object(stream);
+
+    let (width, height) = (right - left, top - bottom);
+    // Move the visible area to the origin, then turn it clockwise as readers display it
+    let to_origin = scale_and_translate(1.0, -left, -bottom);
+    let rotate = match rotation {
+        90 => [0.0, -1.0, 1.0, 0.0, 0.0, width],
+        180 => [-1.0, 0.0, 0.0, -1.0, width, height],
+        270 => [0.0, 1.0, -1.0, 0.0, height, 0.0],
+        _ => [1.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+    };
+    let (width, height) = if rotation == 90 || rotation == 270 {
+        (height, width)
+    } else {
+        (width, height)
+    };
+
+    Ok(PageForm {
+        xobject_id,
+        width,
+        height,
+        to_display: multiply(to_origin, rotate),
+    })
+}
+
+/// Replaces the geometry and content of the page with the given forms drawn onto a
+/// `width` x `height` sheet. Each form is placed with a matrix that maps its displayed page
+/// (as in [`PageForm::to_display`]) onto the sheet, and outlined when `border` is not zero.
+pub(crate) fn draw_forms_on_page(
+    doc: &mut Document,
+    page_id: ObjectId,
+    width: f32,
+    height: f32,
+    forms: &[(&PageForm, Matrix)],
+    border: f32,
+) -> Result<(), ErrorCode> {
+    let mut xobjects = Dictionary::new();
+    let mut content = String::new();
+    for (index, (form, placement)) in forms.iter().enumerate() {
+        let name = format!("P{}", index);
+        let [a, b, c, d, e, f] = multiply(form.to_display, *placement);
+        content.push_str(&format!(
+            "q {} {} {} {} {} {} cm /{} Do Q\n",
+            a, b, c, d, e, f, name
+        ));
+        xobjects.set(name, Object::Reference(form.xobject_id));
+
+        if border > 0.0 {
+            let corners = [
+                (0.0, 0.0),
+                (form.width, 0.0),
+                (form.width, form.height),
+                (0.0, form.height),
+            ]
+            .map(|(x, y)| apply(*placement, x, y));
+            content.push_str(&format!(
+                "q {} w {} {} m {} {} l {} {} l {} {} l h S Q\n",
+                border,
+                corners[0].0,
+                corners[0].1,
+                corners[1].0,
+                corners[1].1,
+                corners[2].0,
+                corners[2].1,
+                corners[3].0,
+                corners[3].1
+            ));
+        }
+    }
+
+    let mut resources = Dictionary::new();
+    resources.set("XObject", Object::Dictionary(xobjects));
+    let content_id = doc.add_object(Stream::new(Dictionary::new(), content.into_bytes()));
+
+    let page = doc
+        .get_object_mut(page_id)
+        .and_then(Object::as_dict_mut)
+        .map_err(|_| ErrorCode::PdfPageNotFound)?;
+    for key in GEOMETRY_KEYS {
+        page.remove(key);
+    }
+    page.remove(b"Group");
+    page.set("MediaBox", box_object([0.0, 0.0, width, height]));
+    page.set("Resources", Object::Dictionary(resources));
+    page.set("Contents", Object::Reference(content_id));
+
+    Ok(())
+}
+
+/// Moves the annotations of `from_page` onto `to_page`, along with content that was redrawn there
+/// through `matrix`. Only the rectangles move; readers scale appearance streams into them.
+pub(crate) fn transform_annotations(
+    doc: &mut Document,
+    from_page: ObjectId,
+    to_page: ObjectId,
+    matrix: Matrix,
+) {
+    let annotations: Vec<Dictionary> = match doc
+        .get_dictionary(from_page)
+        .and_then(|page| page.get(b"Annots"))
+        .and_then(Object::as_array)
+    {
+        Ok(annots) => annots
+            .iter()
+            .filter_map(|annot| doc.dereference(annot).ok())
+            .filter_map(|(_, annot)| annot.as_dict().ok().cloned())
+            .collect(),
+        Err(_) => return,
+    };
+
+    // Annotations are copied, since a page exported twice shares them with its other copy
+    let mut annotation_ids = Vec::new();
+    if from_page != to_page {
+        if let Ok(annots) = doc
+            .get_dictionary(to_page)
+            .and_then(|page| page.get(b"Annots"))
+            .and_then(Object::as_array)
+        {
+            annotation_ids.extend(annots.iter().cloned());
+        }
+    }
+
+    for mut annotation in annotations {
+        annotation.set("P", Object::Reference(to_page));
+
+        for key in [b"Rect".as_slice(), b"QuadPoints".as_slice()] {
+            let Ok(values) = annotation.get(key).and_then(Object::as_array) else {
+                continue;
+            };
+            let values: Vec<f32> = values.iter().filter_map(|v| v.as_float().ok()).collect();
+            let mut transformed: Vec<f32> = values
+                .chunks_exact(2)
+                .flat_map(|point| {
+                    let (x, y) = apply(matrix, point[0], point[1]);
+                    [x, y]
+                })
+                .collect();
+
+            // A rectangle must stay lower left and upper right after rotation
+            if key == b"Rect" && transformed.len() == 4 {
+                transformed = vec![
+                    transformed[0].min(transformed[2]),
+                    transformed[1].min(transformed[3]),
+                    transformed[0].max(transformed[2]),
+                    transformed[1].max(transformed[3]),
+                ];
+            }
+
+            annotation.set(
+                key,
+                Object::Array(transformed.into_iter().map(Object::Real).collect()),
+            );
+        }
+
+        annotation_ids.push(Object::Reference(doc.add_object(annotation)));
+    }
+
+    if let Ok(page) = doc.get_object_mut(to_page).and_then(Object::as_dict_mut) {
+        page.set("Annots", Object::Array(annotation_ids));
+    }
+}
+
+/// Adds an empty `width` x `height` page under `parent_id`.
+pub(crate) fn add_blank_page(
+    doc: &mut Document,
+    parent_id: ObjectId,
+    width: f32,
+    height: f32,
+) -> ObjectId {
+    let mut page = Dictionary::new();
+    page.set("Type", Object::Name(b"Page".to_vec()));
+    page.set("Parent", Object::Reference(parent_id));
+    page.set("MediaBox", box_object([0.0, 0.0, width, height]));
+    page.set("Resources", Object::Dictionary(Dictionary::new()));
+    doc.add_object(page)
+}
+
+/// Scales the page to fit a `width` x `height` sheet, preserving its aspect ratio, centered.
+pub(crate) fn fit_page_to_size(
+    doc: &mut Document,
+    page_id: ObjectId,
+    width: f32,
+    height: f32,
+) -> Result<(), ErrorCode> {
+    let form = page_to_form(doc, page_id)?;
+    let placement = fit_into(form.width, form.height, 0.0, 0.0, width, height);
+    draw_forms_on_page(doc, page_id, width, height, &[(&form, placement)], 0.0)?;
+    transform_annotations(doc, page_id, page_id, multiply(form.to_display, placement));
+
+    Ok(())
+}
+
+fn cell_size(width: f32, height: f32, columns: u32, rows: u32, gap: f32) -> (f32, f32) {
+    (
+        (width - gap * (columns + 1) as f32) / columns as f32,
+        (height - gap * (rows + 1) as f32) / rows as f32,
+    )
+}
+
+/// Page indices in saddle-stitch order, two per side and four per sheet, with `None` for the
+/// blank pages that pad the booklet to a multiple of 4.
+fn booklet_order(count: usize) -> Vec<Option<usize>> {
+    let padded = count.div_ceil(4) * 4;
+    (0..padded / 4)
+        .flat_map(|sheet| {
+            [
+                padded - 1 - 2 * sheet,
+                2 * sheet,
+                2 * sheet + 1,
+                padded - 2 - 2 * sheet,
+            ]
+        })
+        .map(|index| (index < count).then_some(index))
+        .collect()
+}
+
+/// The page indices on each sheet of the layout, cell by cell, with `None` for empty cells.
+fn sheet_sides(layout: PageLayout, count: usize) -> Vec<Vec<Option<usize>>> {
+    let (order, per_sheet) = match layout {
+        PageLayout::NUp { columns, rows, .. } => {
+            ((0..count).map(Some).collect(), (columns * rows) as usize)
+        }
+        PageLayout::Booklet => (booklet_order(count), 2),
+    };
+    order.chunks(per_sheet.max(1)).map(<[_]>::to_vec).collect()
+}
+
+/// For each of `count` pages, the index of the sheet [`impose_pages`] places it on.
+pub(crate) fn sheet_indices(layout: PageLayout, count: usize) -> Vec<usize> {
+    let mut indices = vec![0; count];
+    for (sheet, side) in sheet_sides(layout, count).iter().enumerate() {
+        for &index in side.iter().flatten() {
+            indices[index] = sheet;
+        }
+    }
+    indices
+}
+
+fn retarget_destination(destination: &mut Object, sheet_of: &BTreeMap<ObjectId, ObjectId>) {
+    if let Ok(destination) = destination.as_array_mut() {
+        if let Some(Object::Reference(page_id)) = destination.first_mut() {
+            if let Some(&sheet_id) = sheet_of.get(page_id) {
+                *page_id = sheet_id;
+            }
+        }
+    }
+}
+
+/// Points links on the page that lead to one of the imposed pages at the sheet it was placed on.
+fn retarget_links(
+    doc: &mut Document,
+    page_id: ObjectId,
+    sheet_of: &BTreeMap<ObjectId, ObjectId>,
+) {
+    let annotation_ids: Vec<ObjectId> = match doc
+        .get_dictionary(page_id)
+        .and_then(|page| page.get(b"Annots"))
+        .and_then(Object::as_array)
+    {
+        Ok(annots) => annots
+            .iter()
+            .filter_map(|annot| annot.as_reference().ok())
+            .collect(),
+        Err(_) => return,
+    };
+
+    for annotation_id in annotation_ids {
+        let Ok(annotation) = doc
+            .get_object_mut(annotation_id)
+            .and_then(Object::as_dict_mut)
+        else {
+            continue;
+        };
+        if let Ok(destination) = annotation.get_mut(b"Dest") {
+            retarget_destination(destination, sheet_of);
+        }
+        if let Ok(Object::Dictionary(action)) = annotation.get_mut(b"A") {
+            if let Ok(destination) = action.get_mut(b"D") {
+                retarget_destination(destination, sheet_of);
+            }
+        }
+    }
+}
+
+/// Places the pages onto new sheets under `parent_id` and returns the sheets in order, along with
+/// the sheet each page was placed on. Sheets are `paper` sized, or the size of the first page
+/// without one, turned to whichever orientation fits the pages larger unless `fixed_orientation`
+/// is set. The pages themselves are left unreferenced.
+pub(crate) fn impose_pages(
+    doc: &mut Document,
+    pages: &[ObjectId],
+    parent_id: ObjectId,
+    layout: PageLayout,
+    paper: Option<(f32, f32)>,
+    fixed_orientation: bool,
+) -> Result<(Vec<ObjectId>, BTreeMap<ObjectId, ObjectId>), ErrorCode> {
+    let forms = pages
+        .iter()
+        .map(|&page_id| page_to_form(doc, page_id))
+        .collect::<Result<Vec<_>, _>>()?;
+    let Some(first) = forms.first() else {
+        return Ok((Vec::new(), BTreeMap::new()));
+    };
+
+    let (columns, rows, gap, border) = match layout {
+        PageLayout::NUp {
+            columns,
+            rows,
+            gap,
+            border,
+        } => (columns, rows, gap.max(0.0), border.max(0.0)),
+        PageLayout::Booklet => (2, 1, 0.0, 0.0),
+    };
+    if columns == 0 || rows == 0 {
+        return Err(ErrorCode::PdfLayoutInvalid);
+    }
+
+    let (mut width, mut height) = paper.unwrap_or((first.width, first.height));
+    let fit_scale = |width: f32, height: f32| {
+        let (cell_width, cell_height) = cell_size(width, height, columns, rows, gap);
+        (cell_width / first.width).min(cell_height / first.height)
+    };
+    if !fixed_orientation && fit_scale(height, width) > fit_scale(width, height) {
+        std::mem::swap(&mut width, &mut height);
+    }
+
+    let (cell_width, cell_height) = cell_size(width, height, columns, rows, gap);
+    if cell_width <= 0.0 || cell_height <= 0.0 {
+        return Err(ErrorCode::PdfLayoutInvalid);
+    }
+
+    let mut sheets = Vec::new();
+    let mut sheet_of = BTreeMap::new();
+    for side in sheet_sides(layout, forms.len()) {
+        let placed: Vec<(usize, Matrix)> = side
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, index)| {
+                let index = (*index)?;
+                let form = &forms[index];
+                let (column, row) = (slot as u32 % columns, slot as u32 / columns);
+                let cell_x = gap + column as f32 * (cell_width + gap);
+                let cell_y = height - (row + 1) as f32 * (cell_height + gap);
+                let placement = fit_into(
+                    form.width,
+                    form.height,
+                    cell_x,
+                    cell_y,
+                    cell_width,
+                    cell_height,
+                );
+                Some((index, placement))
+            })
+            .collect();
+
+        let mut sheet = Dictionary::new();
+        sheet.set("Type", Object::Name(b"Page".to_vec()));
+        sheet.set("Parent", Object::Reference(parent_id));
+        let sheet_id = doc.add_object(sheet);
+
+        let placed_forms: Vec<(&PageForm, Matrix)> = placed
+            .iter()
+            .map(|&(index, placement)| (&forms[index], placement))
+            .collect();
+        draw_forms_on_page(doc, sheet_id, width, height, &placed_forms, border)?;
+
+        for &(index, placement) in &placed {
+            let matrix = multiply(forms[index].to_display, placement);
+            transform_annotations(doc, pages[index], sheet_id, matrix);
+            sheet_of.insert(pages[index], sheet_id);
+        }
+        sheets.push(sheet_id);
+    }
+
+    // Links between the pages now lead to the sheets
+    for &sheet_id in &sheets {
+        retarget_links(doc, sheet_id, &sheet_of);
+    }
+
+    Ok((sheets, sheet_of))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The booklet order as page numbers from 1, `-` for blank pages
+    fn sides(count: usize) -> String {
+        booklet_order(count)
+            .iter()
+            .map(|index| index.map_or("-".to_string(), |index| (index + 1).to_string()))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn booklet_of_four_is_one_sheet() {
+        assert_eq!(sides(4), "4 1 2 3");
+    }
+
+    #[test]
+    fn booklet_of_eight_nests_sheets() {
+        assert_eq!(sides(8), "8 1 2 7 6 3 4 5");
+    }
+
+    #[test]
+    fn single_page_booklet_pads_with_blanks() {
+        assert_eq!(sides(1), "- 1 - -");
+    }
+
+    #[test]
+    fn two_page_booklet_keeps_pages_facing() {
+        assert_eq!(sides(2), "- 1 2 -");
+    }
+
+    #[test]
+    fn blanks_go_to_the_back_of_the_booklet() {
+        assert_eq!(sides(3), "- 1 2 3");
+        assert_eq!(sides(5), "- 1 2 - - 3 4 5");
+        assert_eq!(sides(6), "- 1 2 - 6 3 4 5");
+        assert_eq!(sides(7), "- 1 2 7 6 3 4 5");
+    }
+
+    #[test]
+    fn booklet_places_every_page_once() {
+        for count in 1..=17 {
+            let order = booklet_order(count);
+            assert_eq!(order.len() % 4, 0);
+            assert!(order.len() < count + 4);
+
+            let mut placed: Vec<usize> = order.iter().flatten().copied().collect();
+            placed.sort_unstable();
+            assert_eq!(placed, (0..count).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn empty_booklet_has_no_sheets() {
+        assert!(booklet_order(0).is_empty());
+    }
+
+    #[test]
+    fn n_up_sheets_follow_page_order() {
+        let layout = PageLayout::NUp {
+            columns: 2,
+            rows: 2,
+            gap: 0.0,
+            border: 0.0,
+        };
+        assert_eq!(sheet_indices(layout, 6), [0, 0, 0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn booklet_sheets_follow_the_fold() {
+        // Sides of 8 pages: [8 1] [2 7] [6 3] [4 5]
+        let sheets = |count| sheet_indices(PageLayout::Booklet, count);
+        assert_eq!(sheets(8), [0, 1, 2, 3, 3, 2, 1, 0]);
+        assert_eq!(sheets(3), [0, 1, 1]);
+    }
+}
@@ -1,4 +1,24 @@
 pub mod commands;
+pub mod cache;
+pub mod jobs;
+pub mod pdfium;
+pub mod pool;
+pub mod protocol;
+pub mod recipe;
+pub mod settings;
+pub mod watch;
+pub mod workspace;
+pub(crate) mod boxes;
 pub(crate) mod functions;
+pub(crate) mod layout;
+pub(crate) mod output;
+pub(crate) mod preflight;
+pub(crate) mod project;
+pub(crate) mod repair;
 pub(crate) mod structs;
-pub(crate) mod enums;
\ No newline at end of file
+pub(crate) mod sync;
+pub(crate) mod toc;
+pub(crate) mod enums;
+pub(crate) mod error;
+#[cfg(test)]
+pub(crate) mod fixtures;
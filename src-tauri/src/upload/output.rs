@@ -0,0 +1,71 @@
+use lopdf::Document;
+use std::fmt::Display;
+use std::fs;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::upload::error::{CommandError, ErrorCode};
+
+/// A document saved next to where it is going, under a temporary name, until `commit` moves it
+/// into place. Nothing exists under the real name before that, so a crash, a full disk or a
+/// cancel never leaves half a PDF behind. Dropping it without committing removes the file.
+pub(crate) struct PendingOutput {
+    temp_path: PathBuf,
+    output_path: PathBuf,
+}
+
+impl PendingOutput {
+    /// Saves the document to a temporary file in the folder of `output_path` and syncs it to
+    /// disk.
+    pub(crate) fn write(doc: &mut Document, output_path: &Path) -> Result<Self, CommandError> {
+        let directory = output_path.parent().unwrap_or_else(|| Path::new(""));
+        let file_name = output_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        // Hidden, and unique so two exports to the same name never share a temporary file
+        let pending = Self {
+            temp_path: directory.join(format!(".{}.{}.tmp", file_name, Uuid::new_v4())),
+            output_path: output_path.to_path_buf(),
+        };
+
+        let file = fs::File::create(&pending.temp_path).map_err(|e| pending.save_error(e))?;
+        let mut writer = BufWriter::new(file);
+        doc.save_to(&mut writer)
+            .map_err(|e| pending.save_error(e))?;
+        let file = writer
+            .into_inner()
+            .map_err(|e| pending.save_error(e.into_error()))?;
+        file.sync_all().map_err(|e| pending.save_error(e))?;
+
+        Ok(pending)
+    }
+
+    /// Moves the file into place, replacing any file already there in one step.
+    pub(crate) fn commit(self) -> Result<(), CommandError> {
+        fs::rename(&self.temp_path, &self.output_path).map_err(|e| self.save_error(e))?;
+
+        // The rename only survives a crash once the folder is synced, which Windows does not allow
+        #[cfg(unix)]
+        if let Some(directory) = self.output_path.parent() {
+            if let Ok(directory) = fs::File::open(directory) {
+                let _ = directory.sync_all();
+            }
+        }
+        Ok(())
+    }
+
+    fn save_error(&self, cause: impl Display) -> CommandError {
+        ErrorCode::PdfSaveError
+            .because(cause)
+            .with_file(&self.output_path)
+    }
+}
+
+impl Drop for PendingOutput {
+    fn drop(&mut self) {
+        // Already gone after a commit, so this only cleans up after errors and cancels
+        let _ = fs::remove_file(&self.temp_path);
+    }
+}
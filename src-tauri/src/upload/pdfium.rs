@@ -0,0 +1,92 @@
+use pdfium_render::prelude::*;
+use std::{
+    collections::VecDeque,
+    fs,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+use tauri::{AppHandle, Manager, State};
+
+use crate::upload::error::{CommandError, ErrorCode};
+use crate::upload::functions::init_pdfium;
+use crate::upload::sync::lock;
+
+// Enough for the documents a user flips between in the preview, without holding every file open
+const MAX_OPEN_DOCUMENTS: usize = 8;
+
+struct OpenDocument {
+    file_path: String,
+    password: Option<String>,
+    modified: Option<SystemTime>,
+    document: Arc<PdfDocument<'static>>,
+}
+
+/// The pdfium library, bound once at startup, and the documents recently opened for preview.
+/// Documents are kept in least-recently-used order, most recent last.
+pub struct PdfiumState {
+    pdfium: &'static Pdfium,
+    documents: Mutex<VecDeque<OpenDocument>>,
+}
+
+impl PdfiumState {
+    pub(crate) fn new(app: &AppHandle) -> Result<Self, CommandError> {
+        // Bound for the lifetime of the app, which lets open documents outlive any single command
+        let pdfium: &'static Pdfium = Box::leak(Box::new(init_pdfium(app)?));
+
+        Ok(Self {
+            pdfium,
+            documents: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    /// Returns the open document for the path, loading it if it is not cached or changed on disk.
+    pub(crate) fn open(
+        &self,
+        file_path: &str,
+        password: Option<&str>,
+    ) -> Result<Arc<PdfDocument<'static>>, PdfiumError> {
+        let modified = fs::metadata(file_path).and_then(|m| m.modified()).ok();
+
+        {
+            let mut documents = lock(&self.documents);
+            if let Some(position) = documents
+                .iter()
+                .position(|open| open.file_path == file_path && open.password.as_deref() == password)
+            {
+                let open = documents.remove(position).unwrap();
+                if open.modified == modified {
+                    let document = open.document.clone();
+                    documents.push_back(open);
+                    return Ok(document);
+                }
+            }
+        }
+
+        // Load outside the lock so other documents stay available meanwhile
+        let document = Arc::new(self.pdfium.load_pdf_from_file(file_path, password)?);
+
+        let mut documents = lock(&self.documents);
+        documents.retain(|open| open.file_path != file_path);
+        documents.push_back(OpenDocument {
+            file_path: file_path.to_string(),
+            password: password.map(str::to_string),
+            modified,
+            document: document.clone(),
+        });
+        while documents.len() > MAX_OPEN_DOCUMENTS {
+            documents.pop_front();
+        }
+
+        Ok(document)
+    }
+
+    /// Drops the cached document, e.g. before the file is rewritten.
+    pub(crate) fn close(&self, file_path: &str) {
+        lock(&self.documents).retain(|open| open.file_path != file_path);
+    }
+}
+
+pub(crate) fn pdfium_state(app: &AppHandle) -> Result<State<'_, PdfiumState>, CommandError> {
+    app.try_state::<PdfiumState>()
+        .ok_or_else(|| CommandError::new(ErrorCode::PdfiumInitError))
+}
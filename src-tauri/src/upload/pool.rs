@@ -0,0 +1,75 @@
+use std::sync::{Arc, Condvar, Mutex, PoisonError, RwLock};
+
+use crate::upload::error::{CommandError, ErrorCode};
+use crate::upload::sync::{lock, read, write};
+
+/// Limits how many documents render thumbnails at once, and encodes their pages on a shared worker pool.
+///
+/// Pdfium itself is not thread safe: pdfium-render serializes every call into the library, so rendering
+/// stays on the document's own thread. Encoding the rendered bitmaps is where the time goes, and that
+/// runs on the worker pool.
+pub struct RenderPool {
+    encoders: RwLock<Arc<rayon::ThreadPool>>,
+    // (documents rendering, limit)
+    slots: Mutex<(usize, usize)>,
+    slot_freed: Condvar,
+}
+
+pub(crate) struct RenderPermit<'a> {
+    pool: &'a RenderPool,
+}
+
+impl Drop for RenderPermit<'_> {
+    fn drop(&mut self) {
+        lock(&self.pool.slots).0 -= 1;
+        self.pool.slot_freed.notify_one();
+    }
+}
+
+impl RenderPool {
+    pub(crate) fn new() -> Result<Self, CommandError> {
+        let limit = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(4);
+
+        Ok(Self {
+            encoders: RwLock::new(Arc::new(build_encoders(limit)?)),
+            slots: Mutex::new((0, limit)),
+            slot_freed: Condvar::new(),
+        })
+    }
+
+    /// Blocks until fewer than `limit` documents are rendering.
+    pub(crate) fn acquire(&self) -> RenderPermit<'_> {
+        let mut slots = lock(&self.slots);
+        while slots.0 >= slots.1 {
+            slots = self
+                .slot_freed
+                .wait(slots)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        slots.0 += 1;
+        RenderPermit { pool: self }
+    }
+
+    pub(crate) fn encoders(&self) -> Arc<rayon::ThreadPool> {
+        read(&self.encoders).clone()
+    }
+
+    /// Renders already running keep their encoder pool until they finish.
+    pub(crate) fn set_limit(&self, limit: usize) -> Result<(), CommandError> {
+        let limit = limit.max(1);
+        *write(&self.encoders) = Arc::new(build_encoders(limit)?);
+        lock(&self.slots).1 = limit;
+        self.slot_freed.notify_all();
+        Ok(())
+    }
+}
+
+fn build_encoders(threads: usize) -> Result<rayon::ThreadPool, CommandError> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .thread_name(|i| format!("thumbnail-encoder-{}", i))
+        .build()
+        .map_err(|e| ErrorCode::RenderPoolError.because(e))
+}
@@ -0,0 +1,173 @@
+use pdfium_render::prelude::*;
+use std::{collections::HashSet, fs, path::Path};
+use tauri::{AppHandle, Manager};
+
+use crate::upload::cache::hash_file;
+use crate::upload::enums::{ExportEntry, PdfFeature};
+use crate::upload::error::{CommandError, ErrorCode};
+use crate::upload::functions::{check_export_permissions, file_name_from_path, open_error};
+use crate::upload::pdfium::{pdfium_state, PdfiumState};
+use crate::upload::protocol::DocumentRegistry;
+use crate::upload::structs::{LostFeatures, PreflightReport};
+
+// The pages of one source, read with one password
+struct PreflightSource<'a> {
+    file_path: &'a str,
+    password: Option<&'a str>,
+    pages: Vec<usize>,
+}
+
+/// Checks every page of an export against what `download_file` needs, without writing anything.
+/// Problems are collected rather than returned, so the user sees all of them at once.
+pub(crate) fn preflight_entries(
+    app: &AppHandle,
+    entries: &[ExportEntry],
+) -> Result<PreflightReport, CommandError> {
+    let pdfium = pdfium_state(app)?;
+    let registry = app.state::<DocumentRegistry>();
+
+    let mut sources: Vec<PreflightSource> = Vec::new();
+    for entry in entries {
+        let ExportEntry::Page(thumb) = entry else {
+            continue;
+        };
+        let password = thumb.password.as_deref();
+        match sources
+            .iter_mut()
+            .find(|source| source.file_path == thumb.file_path && source.password == password)
+        {
+            Some(source) => source.pages.push(thumb.page_index),
+            None => sources.push(PreflightSource {
+                file_path: &thumb.file_path,
+                password,
+                pages: vec![thumb.page_index],
+            }),
+        }
+    }
+
+    let mut report = PreflightReport {
+        issues: Vec::new(),
+        lost_features: Vec::new(),
+        estimated_size: 0,
+    };
+    let mut checked_features = HashSet::new();
+    for source in &mut sources {
+        source.pages.sort_unstable();
+        source.pages.dedup();
+        check_source(
+            &pdfium,
+            &registry,
+            source,
+            &mut report,
+            &mut checked_features,
+        );
+    }
+
+    Ok(report)
+}
+
+fn check_source<'a>(
+    pdfium: &PdfiumState,
+    registry: &DocumentRegistry,
+    source: &PreflightSource<'a>,
+    report: &mut PreflightReport,
+    checked_features: &mut HashSet<&'a str>,
+) {
+    let file_path = source.file_path;
+    // The file the user opened, which for converted and repaired files is not what is rendered
+    let registered = registry.find_by_render_path(file_path);
+    let source_path = registered
+        .as_ref()
+        .map_or(file_path, |document| document.source_path.as_str());
+
+    if !Path::new(source_path).exists() || !Path::new(file_path).exists() {
+        report
+            .issues
+            .push(CommandError::new(ErrorCode::FileNotFound).with_file(source_path));
+        return;
+    }
+    let loaded_hash = registered
+        .as_ref()
+        .and_then(|document| document.source_hash.as_ref());
+    if let Some(loaded_hash) = loaded_hash {
+        if hash_file(Path::new(source_path)).ok().as_ref() != Some(loaded_hash) {
+            report
+                .issues
+                .push(CommandError::new(ErrorCode::FileModified).with_file(source_path));
+        }
+    }
+
+    let document = match pdfium.open(file_path, source.password) {
+        Ok(document) => document,
+        Err(e) => {
+            let error = open_error(e, source.password.is_some());
+            report.issues.push(error.with_file(source_path));
+            return;
+        }
+    };
+    if let Err(e) = check_export_permissions(pdfium, file_path, source.password) {
+        report.issues.push(e.with_file(source_path));
+    }
+
+    let page_count = document.pages().len() as usize;
+    let (pages, missing): (Vec<usize>, Vec<usize>) = source
+        .pages
+        .iter()
+        .copied()
+        .partition(|&page_index| page_index < page_count);
+    report.issues.extend(missing.into_iter().map(|page_index| {
+        CommandError::new(ErrorCode::PdfPageNotFound)
+            .with_file(source_path)
+            .with_page(page_index)
+    }));
+
+    // Pages take roughly an even share of the file, resources they share are copied once
+    if page_count > 0 {
+        let file_size = fs::metadata(file_path).map_or(0, |metadata| metadata.len());
+        report.estimated_size += file_size * pages.len() as u64 / page_count as u64;
+    }
+
+    if checked_features.insert(file_path) {
+        let features = document_features(&document, &pages);
+        if !features.is_empty() {
+            report.lost_features.push(LostFeatures {
+                file_name: file_name_from_path(source_path),
+                file_path: source_path.to_string(),
+                features,
+            });
+        }
+    }
+}
+
+/// What of the document does not carry over into a merge: only pages are copied, so anything
+/// kept at document level is dropped, signatures no longer match, and links may point at pages
+/// that are not exported.
+fn document_features(document: &PdfDocument, pages: &[usize]) -> Vec<PdfFeature> {
+    let mut features = Vec::new();
+
+    if document.form().is_some() {
+        features.push(PdfFeature::Forms);
+    }
+    let bindings = document.bindings();
+    if bindings.FPDFDoc_GetJavaScriptActionCount(bindings.get_handle_from_document(document)) > 0 {
+        features.push(PdfFeature::JavaScript);
+    }
+    if !document.signatures().is_empty() {
+        features.push(PdfFeature::Signatures);
+    }
+    if !document.attachments().is_empty() {
+        features.push(PdfFeature::Attachments);
+    }
+
+    let document_pages = document.pages();
+    let has_links = pages.iter().any(|&page_index| {
+        document_pages
+            .get(page_index as u16)
+            .is_ok_and(|page| !page.links().is_empty())
+    });
+    if has_links {
+        features.push(PdfFeature::Links);
+    }
+
+    features
+}
@@ -0,0 +1,103 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::upload::cache::hash_file;
+use crate::upload::enums::SourceState;
+use crate::upload::error::{CommandError, ErrorCode};
+use crate::upload::structs::{ProjectData, ProjectSource, ProjectSourceStatus};
+
+pub(crate) const PROJECT_EXTENSION: &str = "ignis";
+
+// Bumped when the format changes in a way older versions cannot read
+const PROJECT_VERSION: u32 = 1;
+
+/// The path with the project extension, unless it already has it.
+pub(crate) fn project_path(path: &str) -> PathBuf {
+    let path = PathBuf::from(path);
+    let has_extension = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(PROJECT_EXTENSION));
+
+    if has_extension {
+        path
+    } else {
+        let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(format!(".{}", PROJECT_EXTENSION));
+        path.with_file_name(file_name)
+    }
+}
+
+/// Lists every file the documents use, hashed as they are now. Whether a source needs a password
+/// is taken over from the sources given.
+fn collect_sources(project: &ProjectData) -> Vec<ProjectSource> {
+    let file_paths = project.documents.iter().flat_map(|document| {
+        let pages = document.pages.iter().map(|page| &page.file_path);
+        std::iter::once(&document.file_path).chain(pages)
+    });
+
+    let mut sources: Vec<ProjectSource> = Vec::new();
+    for file_path in file_paths {
+        if sources.iter().any(|source| &source.file_path == file_path) {
+            continue;
+        }
+
+        let encrypted = project
+            .sources
+            .iter()
+            .any(|source| &source.file_path == file_path && source.encrypted);
+        sources.push(ProjectSource {
+            file_path: file_path.clone(),
+            file_hash: hash_file(Path::new(file_path)).ok(),
+            encrypted,
+        });
+    }
+
+    sources
+}
+
+pub(crate) fn write_project(path: &Path, mut project: ProjectData) -> Result<(), CommandError> {
+    project.version = PROJECT_VERSION;
+    project.sources = collect_sources(&project);
+
+    let content =
+        serde_json::to_vec_pretty(&project).map_err(|e| ErrorCode::ProjectSaveError.because(e))?;
+    fs::write(path, content).map_err(|e| ErrorCode::ProjectSaveError.because(e).with_file(path))
+}
+
+pub(crate) fn read_project(path: &Path) -> Result<ProjectData, CommandError> {
+    let content =
+        fs::read(path).map_err(|e| ErrorCode::ProjectLoadError.because(e).with_file(path))?;
+    let project: ProjectData = serde_json::from_slice(&content)
+        .map_err(|e| ErrorCode::ProjectInvalid.because(e).with_file(path))?;
+
+    if project.version > PROJECT_VERSION {
+        return Err(CommandError::new(ErrorCode::ProjectVersionUnsupported).with_file(path));
+    }
+
+    Ok(project)
+}
+
+/// Compares every source with the file on disk.
+pub(crate) fn check_sources(project: &ProjectData) -> Vec<ProjectSourceStatus> {
+    project
+        .sources
+        .iter()
+        .map(|source| {
+            let path = Path::new(&source.file_path);
+            let state = if !path.exists() {
+                SourceState::Missing
+            } else {
+                match (&source.file_hash, hash_file(path)) {
+                    (Some(saved), Ok(current)) if *saved == current => SourceState::Ok,
+                    // Unreadable now, or not hashed when saved: it cannot be trusted to match
+                    _ => SourceState::Modified,
+                }
+            };
+
+            ProjectSourceStatus {
+                file_path: source.file_path.clone(),
+                state,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,267 @@
+use std::{collections::HashMap, sync::Mutex};
+use tauri::{
+    http::{header, Request, Response, StatusCode},
+    AppHandle, Manager,
+};
+use uuid::Uuid;
+
+use crate::upload::boxes::page_display_size;
+use crate::upload::cache::ThumbnailCache;
+use crate::upload::enums::{ImageQuality, OutputFormat};
+use crate::upload::error::{CommandError, ErrorCode};
+use crate::upload::functions::{
+    encode_image, fit_region, gen_image_bitmap, gen_region_bitmap, get_file_ext, region_pixel_size,
+    open_error, render_thumbnail_image, thumbnail_cache_key, THUMBNAIL_HEIGHT,
+};
+use crate::upload::pdfium::pdfium_state;
+use crate::upload::settings::RenderSettings;
+use crate::upload::structs::RegionRect;
+use crate::upload::sync::lock;
+
+pub(crate) const PROTOCOL_NAME: &str = "ignis";
+
+/// What the `ignis://` protocol needs to render the pages of a document.
+#[derive(Clone)]
+pub(crate) struct RegisteredDocument {
+    pub render_path: String,
+    pub password: Option<String>,
+    // None for password-protected documents, which are never cached on disk
+    pub file_hash: Option<String>,
+    // The file that was opened and its hash at the time, to tell whether it changed since
+    pub source_path: String,
+    pub source_hash: Option<String>,
+}
+
+/// Documents that can be served over the `ignis://` protocol, keyed by document id.
+#[derive(Default)]
+pub struct DocumentRegistry {
+    documents: Mutex<HashMap<String, RegisteredDocument>>,
+}
+
+impl DocumentRegistry {
+    pub(crate) fn register(&self, document_id: &str, document: RegisteredDocument) {
+        lock(&self.documents).insert(document_id.to_string(), document);
+    }
+
+    /// Returns the id of an already registered document with this path and password, or registers it.
+    pub(crate) fn register_path(&self, render_path: &str, password: Option<&str>) -> String {
+        let mut documents = lock(&self.documents);
+        if let Some((id, _)) = documents.iter().find(|(_, document)| {
+            document.render_path == render_path && document.password.as_deref() == password
+        }) {
+            return id.clone();
+        }
+
+        let id = Uuid::new_v4().to_string();
+        documents.insert(
+            id.clone(),
+            RegisteredDocument {
+                render_path: render_path.to_string(),
+                password: password.map(str::to_string),
+                file_hash: None,
+                source_path: render_path.to_string(),
+                source_hash: None,
+            },
+        );
+        id
+    }
+
+    pub(crate) fn unregister(&self, document_id: &str) {
+        lock(&self.documents).remove(document_id);
+    }
+
+    pub(crate) fn get(&self, document_id: &str) -> Option<RegisteredDocument> {
+        lock(&self.documents).get(document_id).cloned()
+    }
+
+    /// A document rendered from this path, preferring one whose source hash is known.
+    pub(crate) fn find_by_render_path(&self, render_path: &str) -> Option<RegisteredDocument> {
+        let documents = lock(&self.documents);
+        let mut matching = documents
+            .values()
+            .filter(|document| document.render_path == render_path);
+        let first = matching.clone().next().cloned();
+        matching
+            .find(|document| document.source_hash.is_some())
+            .cloned()
+            .or(first)
+    }
+}
+
+/// `query` takes `w` or `h` for the target size in pixels, and `q` (`low` or `high`) for the
+/// render scale. Without a size the page is rendered at its own size in points.
+/// Alternatively `region=x,y,width,height` (in points) and `scale` (pixels per point) render a
+/// single tile of the page.
+pub(crate) fn page_url(document_id: &str, page_index: usize, query: &str) -> String {
+    // Custom protocols are served over http on Windows and Android
+    #[cfg(any(target_os = "windows", target_os = "android"))]
+    let base = format!("http://{}.localhost", PROTOCOL_NAME);
+
+    #[cfg(not(any(target_os = "windows", target_os = "android")))]
+    let base = format!("{}://localhost", PROTOCOL_NAME);
+
+    format!("{}/page/{}/{}?{}", base, document_id, page_index, query)
+}
+
+pub(crate) fn handle_page_request(app: &AppHandle, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let response = match serve_page(app, request) {
+        Ok((buffer, format)) => Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, format.mime_type())
+            .header(header::CACHE_CONTROL, "private, max-age=3600")
+            .body(buffer),
+        // The same error body the commands return, so the frontend can show it the same way
+        Err((status, error)) => Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&error).unwrap_or_default()),
+    };
+
+    response.unwrap_or_else(|_| {
+        let mut response = Response::new(Vec::new());
+        *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        response
+    })
+}
+
+fn serve_page(
+    app: &AppHandle,
+    request: &Request<Vec<u8>>,
+) -> Result<(Vec<u8>, OutputFormat), (StatusCode, CommandError)> {
+    let not_found = |error: CommandError| (StatusCode::NOT_FOUND, error);
+    let server_error = |error: CommandError| (StatusCode::INTERNAL_SERVER_ERROR, error);
+
+    // /page/{document_id}/{page_index}
+    let segments: Vec<&str> = request.uri().path().trim_matches('/').split('/').collect();
+    let (document_id, page_index) = match segments.as_slice() {
+        ["page", document_id, page_index] => (
+            *document_id,
+            page_index
+                .parse::<usize>()
+                .map_err(|_| not_found(ErrorCode::PdfPageNotFound.into()))?,
+        ),
+        _ => return Err(not_found(ErrorCode::ResourceNotFound.into())),
+    };
+
+    let params: HashMap<&str, &str> = request
+        .uri()
+        .query()
+        .unwrap_or("")
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .collect();
+    let width = params.get("w").and_then(|w| w.parse::<f32>().ok());
+    let height = params.get("h").and_then(|h| h.parse::<f32>().ok());
+    let quality = match params.get("q") {
+        Some(&"high") => ImageQuality::High,
+        _ => ImageQuality::Low,
+    };
+    let region = params.get("region").and_then(|region| {
+        let values: Vec<f32> = region.split(',').filter_map(|v| v.parse().ok()).collect();
+        match values.as_slice() {
+            [x, y, width, height] => Some(RegionRect {
+                x: *x,
+                y: *y,
+                width: *width,
+                height: *height,
+            }),
+            _ => None,
+        }
+    });
+    let scale = params.get("scale").and_then(|s| s.parse::<f32>().ok()).unwrap_or(1.0);
+
+    let document = app
+        .state::<DocumentRegistry>()
+        .get(document_id)
+        .ok_or_else(|| not_found(ErrorCode::ResourceNotFound.into()))?;
+
+    // Thumbnails are served from, and written to, the disk cache
+    let is_thumbnail = region.is_none()
+        && width.is_none()
+        && height == Some(THUMBNAIL_HEIGHT)
+        && quality == ImageQuality::Low;
+    let settings = app.state::<RenderSettings>().get();
+    let encoding = if is_thumbnail {
+        settings.thumbnails
+    } else {
+        settings.full_res
+    };
+
+    let file_ext = get_file_ext(&document.render_path).map_err(server_error)?;
+    if file_ext == "png" || file_ext == "jpg" || file_ext == "jpeg" || file_ext == "webp" {
+        // Source images are served as they are, only re-encoded
+        let mut image = image::open(&document.render_path).map_err(|e| {
+            server_error(ErrorCode::PdfLoadError.because(e).with_file(&document.render_path))
+        })?;
+
+        // Images are converted to PDF at one point per pixel, so regions map directly onto them
+        if let Some(region) = region {
+            let (region, scale) = fit_region(image.width() as f32, image.height() as f32, region, scale)
+                .ok_or_else(|| not_found(ErrorCode::PdfRegionInvalid.into()))?;
+            let (tile_width, tile_height) = region_pixel_size(region, scale);
+            image = image
+                .crop_imm(
+                    region.x as u32,
+                    region.y as u32,
+                    region.width.ceil() as u32,
+                    region.height.ceil() as u32,
+                )
+                .resize_exact(tile_width, tile_height, image::imageops::FilterType::Triangle);
+        }
+
+        let buffer = encode_image(&image, encoding).map_err(server_error)?;
+        return Ok((buffer, encoding.format));
+    }
+
+    let pdfium = pdfium_state(app).map_err(server_error)?;
+    let pdf = pdfium
+        .open(&document.render_path, document.password.as_deref())
+        .map_err(|e| {
+            let error = open_error(e, document.password.is_some());
+            server_error(error.with_file(&document.render_path))
+        })?;
+    let page = pdf.pages().get(page_index as u16).map_err(|_| {
+        not_found(CommandError::new(ErrorCode::PdfPageNotFound).with_page(page_index))
+    })?;
+
+    if is_thumbnail {
+        let cache = app.state::<ThumbnailCache>();
+        let key = thumbnail_cache_key(document.file_hash.as_deref(), page_index, encoding);
+        if let Some((buffer, _, _)) = key.as_ref().and_then(|key| cache.get(key)) {
+            return Ok((buffer, encoding.format));
+        }
+
+        let image = render_thumbnail_image(&page).map_err(server_error)?;
+        let buffer = encode_image(&image, encoding).map_err(server_error)?;
+        if let Some(key) = &key {
+            cache.put(key, &buffer);
+        }
+        return Ok((buffer, encoding.format));
+    }
+
+    let (page_width, page_height) = page_display_size(&page);
+
+    if let Some(region) = region {
+        // The scale is capped again, whatever the url asks for
+        let (region, scale) = fit_region(page_width, page_height, region, scale)
+            .ok_or_else(|| not_found(ErrorCode::PdfRegionInvalid.into()))?;
+        let bitmap = gen_region_bitmap(&page, region, scale).map_err(server_error)?;
+        let image = image::DynamicImage::from(bitmap.as_image());
+        let buffer = encode_image(&image, encoding).map_err(server_error)?;
+
+        return Ok((buffer, encoding.format));
+    }
+
+    let aspect_ratio = page_width / page_height;
+    let (target_width, target_height) = match (width, height) {
+        (Some(width), _) => (width, width / aspect_ratio),
+        (None, Some(height)) => (height * aspect_ratio, height),
+        (None, None) => (page_width, page_height),
+    };
+
+    let bitmap = gen_image_bitmap(&page, target_width, target_height, quality).map_err(server_error)?;
+    let image = image::DynamicImage::from(bitmap.as_image());
+    let buffer = encode_image(&image, encoding).map_err(server_error)?;
+
+    Ok((buffer, encoding.format))
+}
@@ -0,0 +1,377 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+use tauri::{AppHandle, Manager};
+use uuid::Uuid;
+
+use crate::upload::enums::{ExportEntry, InputOrder};
+use crate::upload::error::{CommandError, ErrorCode};
+use crate::upload::functions::{
+    convert_image_to_pdf, convert_text_to_pdf, get_file_ext, open_error,
+};
+use crate::upload::pdfium::pdfium_state;
+use crate::upload::structs::{MergeRecipe, RecipeInput, ThumbnailDownloadData};
+use crate::upload::sync::lock;
+use crate::upload::workspace::TempWorkspace;
+
+// Files a pattern picks up; anything else in the folder is left alone
+const SUPPORTED_EXTENSIONS: [&str; 7] = ["pdf", "txt", "md", "png", "jpg", "jpeg", "webp"];
+
+/// Saved recipes, kept in a JSON file in the app config directory.
+pub struct RecipeStore {
+    path: PathBuf,
+    recipes: Mutex<Vec<MergeRecipe>>,
+}
+
+impl RecipeStore {
+    pub(crate) fn new(app: &AppHandle) -> Result<Self, String> {
+        let dir = app
+            .path()
+            .app_config_dir()
+            .map_err(|e| format!("Could not get config directory: {}", e))?;
+        fs::create_dir_all(&dir).map_err(|e| format!("Could not create config directory: {}", e))?;
+        let path = dir.join("recipes.json");
+
+        // A damaged file must not keep the app from starting; it is replaced on the next save
+        let recipes = fs::read(&path)
+            .ok()
+            .and_then(|content| serde_json::from_slice(&content).ok())
+            .unwrap_or_default();
+
+        Ok(Self {
+            path,
+            recipes: Mutex::new(recipes),
+        })
+    }
+
+    pub(crate) fn list(&self) -> Vec<MergeRecipe> {
+        lock(&self.recipes).clone()
+    }
+
+    pub(crate) fn get(&self, recipe_id: &str) -> Option<MergeRecipe> {
+        lock(&self.recipes)
+            .iter()
+            .find(|recipe| recipe.id == recipe_id)
+            .cloned()
+    }
+
+    /// Adds the recipe, or replaces the one with the same id. Returns it with its id set.
+    pub(crate) fn save(&self, mut recipe: MergeRecipe) -> Result<MergeRecipe, CommandError> {
+        if recipe.id.is_empty() {
+            recipe.id = Uuid::new_v4().to_string();
+        }
+
+        let mut recipes = lock(&self.recipes);
+        let mut updated = recipes.clone();
+        match updated.iter_mut().find(|saved| saved.id == recipe.id) {
+            Some(saved) => *saved = recipe.clone(),
+            None => updated.push(recipe.clone()),
+        }
+
+        self.write(&updated)?;
+        *recipes = updated;
+        Ok(recipe)
+    }
+
+    /// Returns `false` if there was no recipe with this id.
+    pub(crate) fn delete(&self, recipe_id: &str) -> Result<bool, CommandError> {
+        let mut recipes = lock(&self.recipes);
+        if !recipes.iter().any(|recipe| recipe.id == recipe_id) {
+            return Ok(false);
+        }
+
+        let updated: Vec<MergeRecipe> = recipes
+            .iter()
+            .filter(|recipe| recipe.id != recipe_id)
+            .cloned()
+            .collect();
+        self.write(&updated)?;
+        *recipes = updated;
+        Ok(true)
+    }
+
+    fn write(&self, recipes: &[MergeRecipe]) -> Result<(), CommandError> {
+        let content =
+            serde_json::to_vec_pretty(recipes).map_err(|e| ErrorCode::RecipeSaveError.because(e))?;
+        fs::write(&self.path, content)
+            .map_err(|e| ErrorCode::RecipeSaveError.because(e).with_file(&self.path))
+    }
+}
+
+fn has_wildcard(pattern: &str) -> bool {
+    pattern.contains(['*', '?'])
+}
+
+/// Matches a file name against a pattern where `*` stands for any run of characters and `?` for
+/// a single one. Case is ignored, so `*.jpg` also picks up `SCAN.JPG`.
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was seen and how much of the name it has taken so far
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                // Let the `*` take one more character and try again from there
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// The files an input stands for, in its order. Wildcards are only allowed in the file name.
+fn resolve_input(folder: &Path, input: &RecipeInput) -> Result<Vec<PathBuf>, CommandError> {
+    let path = folder.join(&input.pattern);
+    let pattern = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| CommandError::new(ErrorCode::RecipeInputInvalid).with_file(&path))?;
+
+    // A single named file is expected to be there, e.g. the cover every packet starts with
+    if !has_wildcard(pattern) {
+        return if path.is_file() {
+            Ok(vec![path])
+        } else {
+            Err(CommandError::new(ErrorCode::RecipeInputMissing).with_file(&path))
+        };
+    }
+
+    let dir = path.parent().unwrap_or(folder);
+    let mut files: Vec<(PathBuf, Option<SystemTime>)> = fs::read_dir(dir)
+        .map_err(|e| ErrorCode::RecipeFolderError.because(e).with_file(dir))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            let supported = get_file_ext(&name)
+                .is_ok_and(|ext| SUPPORTED_EXTENSIONS.contains(&ext.as_str()));
+            supported && matches_pattern(pattern, &name)
+        })
+        .map(|entry| {
+            let modified = entry.metadata().and_then(|m| m.modified()).ok();
+            (entry.path(), modified)
+        })
+        .collect();
+
+    match input.order {
+        InputOrder::Name => files.sort_by_key(|(path, _)| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().to_lowercase())
+        }),
+        InputOrder::Modified => files.sort_by_key(|(_, modified)| *modified),
+    }
+
+    Ok(files.into_iter().map(|(path, _)| path).collect())
+}
+
+/// Every file the recipe merges, in order. A file picked up by an earlier input is not added
+/// again, so `cover.pdf` followed by `*.pdf` has the cover only once. Files in `exclude` are
+/// skipped, e.g. earlier outputs of the recipe that ended up in its own folder.
+pub(crate) fn resolve_recipe(
+    recipe: &MergeRecipe,
+    exclude: &[PathBuf],
+) -> Result<Vec<PathBuf>, CommandError> {
+    let folder = Path::new(&recipe.folder);
+    let mut files: Vec<PathBuf> = Vec::new();
+
+    for input in &recipe.inputs {
+        for file in resolve_input(folder, input)? {
+            if !files.contains(&file) && !exclude.contains(&file) {
+                files.push(file);
+            }
+        }
+    }
+
+    if files.is_empty() {
+        return Err(CommandError::new(ErrorCode::RecipeNoInputs).with_file(folder));
+    }
+    Ok(files)
+}
+
+/// The input files as export entries, every page of each in order. Text and image files are
+/// converted to temporary PDFs first, which are returned for the caller to remove after the merge.
+pub(crate) fn recipe_entries(
+    app: &AppHandle,
+    files: &[PathBuf],
+) -> Result<(Vec<ExportEntry>, Vec<PathBuf>), CommandError> {
+    let mut entries = Vec::new();
+    let mut temp_files = Vec::new();
+
+    for file in files {
+        if let Err(e) = add_file_entries(app, file, &mut entries, &mut temp_files) {
+            remove_temp_files(app, &temp_files);
+            return Err(e);
+        }
+    }
+
+    Ok((entries, temp_files))
+}
+
+/// Removes the temporary PDFs, closing them in pdfium first, which would otherwise keep the
+/// merged ones open and render from files that are gone.
+pub(crate) fn remove_temp_files(app: &AppHandle, temp_files: &[PathBuf]) {
+    let pdfium = pdfium_state(app).ok();
+    for temp_file in temp_files {
+        if let Some(pdfium) = &pdfium {
+            pdfium.close(&temp_file.to_string_lossy());
+        }
+        let _ = fs::remove_file(temp_file);
+    }
+}
+
+fn add_file_entries(
+    app: &AppHandle,
+    file: &Path,
+    entries: &mut Vec<ExportEntry>,
+    temp_files: &mut Vec<PathBuf>,
+) -> Result<(), CommandError> {
+    let file_path = file.to_string_lossy().to_string();
+    let pdfium = pdfium_state(app)?;
+    let file_ext = get_file_ext(&file_path)?;
+
+    let render_path = if file_ext == "pdf" {
+        file_path.clone()
+    } else {
+        let temp_pdf_path = app.state::<TempWorkspace>().scratch_path();
+        temp_files.push(temp_pdf_path.clone());
+        if file_ext == "txt" || file_ext == "md" {
+            let text = fs::read_to_string(file)
+                .map_err(|e| ErrorCode::PdfLoadError.because(e).with_file(file))?;
+            convert_text_to_pdf(app, &text, &temp_pdf_path)
+        } else {
+            convert_image_to_pdf(app, &file_path, &temp_pdf_path)
+        }
+        .map_err(|e| e.with_file(file))?;
+        temp_pdf_path.to_string_lossy().to_string()
+    };
+
+    // Recipes run unattended, so password-protected sources cannot be opened
+    let page_count = match pdfium.open(&render_path, None) {
+        Ok(document) => document.pages().len() as usize,
+        Err(e) => return Err(open_error(e, false).with_file(file)),
+    };
+    if render_path != file_path {
+        pdfium.close(&render_path);
+    }
+
+    entries.extend((0..page_count).map(|page_index| {
+        ExportEntry::Page(ThumbnailDownloadData {
+            file_path: render_path.clone(),
+            page_index,
+            password: None,
+            crop: None,
+        })
+    }));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::time::Duration;
+
+    // A new folder in the temp directory holding the named files, each modified the given number
+    // of seconds after the epoch
+    fn folder_with(files: &[(&str, u64)]) -> PathBuf {
+        let folder = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        fs::create_dir_all(&folder).unwrap();
+        for (name, modified) in files {
+            let file = File::create(folder.join(name)).unwrap();
+            file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(*modified))
+                .unwrap();
+        }
+        folder
+    }
+
+    fn resolved_names(folder: &Path, pattern: &str, order: InputOrder) -> Vec<String> {
+        let input = RecipeInput {
+            pattern: pattern.to_string(),
+            order,
+        };
+        resolve_input(folder, &input)
+            .unwrap()
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn star_matches_any_run_of_characters() {
+        assert!(matches_pattern("*.pdf", "report.pdf"));
+        assert!(matches_pattern("*.pdf", ".pdf"));
+        assert!(matches_pattern("scan*", "scan"));
+        assert!(matches_pattern("a*b*c", "axxbyyc"));
+        assert!(matches_pattern("*", "anything.txt"));
+        assert!(!matches_pattern("*.pdf", "report.pdf.txt"));
+        assert!(!matches_pattern("a*b*c", "axxbyy"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_character() {
+        assert!(matches_pattern("page?.pdf", "page1.pdf"));
+        assert!(matches_pattern("page??.pdf", "page12.pdf"));
+        assert!(!matches_pattern("page?.pdf", "page.pdf"));
+        assert!(!matches_pattern("page?.pdf", "page12.pdf"));
+    }
+
+    #[test]
+    fn patterns_ignore_case() {
+        assert!(matches_pattern("*.jpg", "SCAN.JPG"));
+        assert!(matches_pattern("Invoice-?.PDF", "invoice-7.pdf"));
+        assert!(matches_pattern("*ä*", "Bär.pdf"));
+    }
+
+    #[test]
+    fn inputs_by_name_sort_case_insensitively() {
+        let folder = folder_with(&[("b.pdf", 1), ("A.pdf", 3), ("c.PDF", 2), ("notes.doc", 0)]);
+        let names = resolved_names(&folder, "*", InputOrder::Name);
+        fs::remove_dir_all(&folder).unwrap();
+
+        assert_eq!(names, ["A.pdf", "b.pdf", "c.PDF"]);
+    }
+
+    #[test]
+    fn inputs_by_modified_sort_oldest_first() {
+        let folder = folder_with(&[("b.pdf", 1), ("A.pdf", 3), ("c.PDF", 2)]);
+        let names = resolved_names(&folder, "*.pdf", InputOrder::Modified);
+        fs::remove_dir_all(&folder).unwrap();
+
+        assert_eq!(names, ["b.pdf", "c.PDF", "A.pdf"]);
+    }
+
+    #[test]
+    fn missing_named_input_is_an_error() {
+        let folder = folder_with(&[]);
+        let input = RecipeInput {
+            pattern: "cover.pdf".to_string(),
+            order: InputOrder::Name,
+        };
+        let result = resolve_input(&folder, &input);
+        fs::remove_dir_all(&folder).unwrap();
+
+        assert_eq!(result.unwrap_err().code, ErrorCode::RecipeInputMissing);
+    }
+}
@@ -0,0 +1,343 @@
+use lopdf::{dictionary, Document, Object, ObjectId};
+use std::{collections::BTreeMap, fs, panic, path::Path};
+
+use crate::upload::error::{CommandError, ErrorCode};
+use crate::upload::output::PendingOutput;
+use crate::upload::structs::RepairReport;
+
+// Inherited page attributes (PDF 32000-1, 7.7.3.4), copied onto pages that lose their parent
+const INHERITED_KEYS: [&[u8]; 4] = [b"Resources", b"MediaBox", b"CropBox", b"Rotate"];
+
+/// Repairs a damaged PDF and writes the result to `output_path`, which can then be opened in its
+/// place.
+pub(crate) fn repair_file(
+    file_path: &Path,
+    output_path: &Path,
+) -> Result<RepairReport, CommandError> {
+    let buffer =
+        fs::read(file_path).map_err(|e| ErrorCode::PdfLoadError.because(e).with_file(file_path))?;
+    let (mut doc, report) = repair_document(&buffer).map_err(|e| e.with_file(file_path))?;
+    PendingOutput::write(&mut doc, output_path).and_then(PendingOutput::commit)?;
+    Ok(report)
+}
+
+/// Rebuilds a document whose cross-reference table or trailer is broken. The objects are found
+/// by scanning the file for `N G obj`, those that cannot be parsed are dropped, and the page
+/// tree is rebuilt from the page objects if it no longer leads to all of them.
+pub(crate) fn repair_document(buffer: &[u8]) -> Result<(Document, RepairReport), CommandError> {
+    let (buffer, offsets) = scan_objects(buffer);
+    if offsets.is_empty() {
+        return Err(ErrorCode::PdfLoadError.because("no objects found"));
+    }
+
+    let rebuilt = with_rebuilt_xref(&buffer, &offsets);
+    let mut doc = match panic::catch_unwind(|| Document::load_mem(&rebuilt)) {
+        Ok(result) => result.map_err(|e| ErrorCode::PdfLoadError.because(e))?,
+        Err(_) => {
+            return Err(ErrorCode::PdfLoadError.because("the repaired file could not be parsed"))
+        }
+    };
+
+    // Without the trailer there is no key, so the content would stay unreadable
+    if doc.objects.values().any(is_encryption_dict) {
+        return Err(ErrorCode::PdfLoadError.because("encrypted files cannot be repaired"));
+    }
+
+    let objects_lost = offsets
+        .iter()
+        .filter(|&(&id, &(generation, _))| !doc.objects.contains_key(&(id, generation)))
+        .count();
+
+    recover_page_tree(&mut doc)?;
+
+    Ok((doc, RepairReport { objects_lost }))
+}
+
+/// Where each object starts, by object number, relative to the `%PDF-` header of the returned
+/// buffer. Files without a header get one. Where an object is defined more than once the last
+/// definition wins, as it does for incremental updates.
+fn scan_objects(buffer: &[u8]) -> (Vec<u8>, BTreeMap<u32, (u16, usize)>) {
+    let buffer = match buffer.windows(5).position(|w| w == b"%PDF-") {
+        Some(start) => buffer[start..].to_vec(),
+        None => [b"%PDF-1.7\n".as_slice(), buffer].concat(),
+    };
+
+    let mut offsets = BTreeMap::new();
+    let mut position = 0;
+    while let Some(found) = buffer[position..].windows(3).position(|w| w == b"obj") {
+        let keyword = position + found;
+        position = keyword + 3;
+
+        // `obj` must stand on its own, which also rules out `endobj`
+        if buffer
+            .get(keyword + 3)
+            .is_some_and(|c| c.is_ascii_alphanumeric())
+        {
+            continue;
+        }
+        if let Some((id, generation, start)) = object_header(&buffer, keyword) {
+            offsets.insert(id, (generation, start));
+        }
+    }
+
+    (buffer, offsets)
+}
+
+/// Reads `N G` backwards from the `obj` keyword at `keyword`, returning the object number,
+/// generation and where the header starts.
+fn object_header(buffer: &[u8], keyword: usize) -> Option<(u32, u16, usize)> {
+    let skip_space = |mut end: usize| {
+        while end > 0 && buffer[end - 1].is_ascii_whitespace() {
+            end -= 1;
+        }
+        end
+    };
+    let digits = |end: usize| {
+        let mut start = end;
+        while start > 0 && buffer[start - 1].is_ascii_digit() {
+            start -= 1;
+        }
+        (start < end).then_some(start)
+    };
+
+    let generation_end = skip_space(keyword);
+    let generation_start = digits(generation_end)?;
+    let id_end = skip_space(generation_start);
+    let id_start = digits(id_end)?;
+    if generation_end == keyword || id_end == generation_start {
+        return None;
+    }
+    // Anything but whitespace before the number means it belongs to something else
+    if id_start > 0 && !buffer[id_start - 1].is_ascii_whitespace() {
+        return None;
+    }
+
+    let id = std::str::from_utf8(&buffer[id_start..id_end])
+        .ok()?
+        .parse()
+        .ok()?;
+    let generation = std::str::from_utf8(&buffer[generation_start..generation_end])
+        .ok()?
+        .parse()
+        .ok()?;
+    Some((id, generation, id_start))
+}
+
+/// The buffer with a new cross-reference table and trailer appended, which take precedence
+/// over whatever is left of the old ones.
+fn with_rebuilt_xref(buffer: &[u8], offsets: &BTreeMap<u32, (u16, usize)>) -> Vec<u8> {
+    let size = offsets.keys().next_back().map_or(1, |id| id + 1);
+    let mut rebuilt = buffer.to_vec();
+    let xref_start = rebuilt.len() + 1;
+
+    let mut xref = format!("\nxref\n0 {}\n", size);
+    for id in 0..size {
+        // Every entry is exactly 20 bytes
+        match offsets.get(&id) {
+            Some((generation, offset)) => {
+                xref.push_str(&format!("{:010} {:05} n\r\n", offset, generation))
+            }
+            None => xref.push_str("0000000000 65535 f\r\n"),
+        }
+    }
+    xref.push_str(&format!(
+        "trailer\n<< /Size {} >>\nstartxref\n{}\n%%EOF\n",
+        size, xref_start
+    ));
+
+    rebuilt.extend_from_slice(xref.as_bytes());
+    rebuilt
+}
+
+fn is_encryption_dict(object: &Object) -> bool {
+    object
+        .as_dict()
+        .is_ok_and(|dict| dict.has(b"Filter") && dict.has(b"O") && dict.has(b"U"))
+}
+
+/// Points the trailer at the catalog, and gives the catalog a new page tree if its own misses
+/// some of the pages found in the file, or the catalog is gone altogether.
+fn recover_page_tree(doc: &mut Document) -> Result<(), CommandError> {
+    let catalog_id = doc
+        .objects
+        .iter()
+        .find(|(_, object)| object.as_dict().is_ok_and(|dict| dict.has_type(b"Catalog")))
+        .map(|(&id, _)| id);
+    if let Some(catalog_id) = catalog_id {
+        doc.trailer.set("Root", catalog_id);
+    }
+
+    // Ordered by object number, which is the order most writers create pages in
+    let page_ids: Vec<ObjectId> = doc
+        .objects
+        .iter()
+        .filter(|(_, object)| object.as_dict().is_ok_and(|dict| dict.has_type(b"Page")))
+        .map(|(&id, _)| id)
+        .collect();
+    if page_ids.is_empty() {
+        return Err(ErrorCode::PdfLoadError.because("no pages found"));
+    }
+    if catalog_id.is_some() && doc.get_pages().len() >= page_ids.len() {
+        return Ok(());
+    }
+
+    let pages_id = doc.new_object_id();
+    for &page_id in &page_ids {
+        let inherited = inherited_attributes(doc, page_id);
+        if let Ok(page) = doc.get_object_mut(page_id).and_then(Object::as_dict_mut) {
+            for (key, value) in inherited {
+                page.set(key, value);
+            }
+            page.set("Parent", pages_id);
+        }
+    }
+
+    let kids: Vec<Object> = page_ids.iter().map(|&id| Object::Reference(id)).collect();
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => page_ids.len() as i64,
+        }),
+    );
+
+    let catalog_id =
+        catalog_id.unwrap_or_else(|| doc.add_object(dictionary! { "Type" => "Catalog" }));
+    doc.trailer.set("Root", catalog_id);
+    if let Ok(catalog) = doc.get_dictionary_mut(catalog_id) {
+        catalog.set("Pages", pages_id);
+    }
+    Ok(())
+}
+
+/// The inherited attributes the page does not set itself, taken from its ancestors as far as
+/// they still exist.
+fn inherited_attributes(doc: &Document, page_id: ObjectId) -> Vec<(Vec<u8>, Object)> {
+    let Ok(page) = doc.get_dictionary(page_id) else {
+        return Vec::new();
+    };
+
+    let mut inherited: Vec<(Vec<u8>, Object)> = Vec::new();
+    let mut parent = page.get(b"Parent").and_then(Object::as_reference).ok();
+    let mut seen = vec![page_id];
+    while let Some(parent_id) = parent.filter(|id| !seen.contains(id)) {
+        seen.push(parent_id);
+        let Ok(node) = doc.get_dictionary(parent_id) else {
+            break;
+        };
+        for key in INHERITED_KEYS {
+            let missing = !page.has(key) && !inherited.iter().any(|(k, _)| k == key);
+            if let (true, Ok(value)) = (missing, node.get(key)) {
+                inherited.push((key.to_vec(), value.clone()));
+            }
+        }
+        parent = node.get(b"Parent").and_then(Object::as_reference).ok();
+    }
+    inherited
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::upload::fixtures::{fixture_path, sample_bytes};
+
+    // Two page documents in `tests/fixtures`, each damaged in its own way
+    const DAMAGED_FIXTURES: [&str; 4] = [
+        "truncated.pdf",
+        "bad_xref.pdf",
+        "missing_root.pdf",
+        "reference_loop.pdf",
+    ];
+
+    fn repair_fixture(name: &str) -> Result<(Document, RepairReport), CommandError> {
+        repair_document(&fs::read(fixture_path(name)).unwrap())
+    }
+
+    fn find(buffer: &[u8], needle: &[u8]) -> usize {
+        buffer
+            .windows(needle.len())
+            .rposition(|w| w == needle)
+            .expect("sample contains the text")
+    }
+
+    // The buffer with the text at `at` overwritten
+    fn overwrite(mut buffer: Vec<u8>, at: usize, text: &[u8]) -> Vec<u8> {
+        buffer[at..at + text.len()].copy_from_slice(text);
+        buffer
+    }
+
+    #[test]
+    fn file_without_xref_is_repaired() {
+        let buffer = sample_bytes(3);
+        let damaged = buffer[..find(&buffer, b"\nxref\n")].to_vec();
+        assert!(Document::load_mem(&damaged).is_err());
+
+        let (doc, report) = repair_document(&damaged).unwrap();
+        assert_eq!(doc.get_pages().len(), 3);
+        assert_eq!(report.objects_lost, 0);
+    }
+
+    #[test]
+    fn wrong_xref_offset_is_repaired() {
+        let buffer = sample_bytes(3);
+        // Object 1 is the page tree, which then seems to start in the header
+        let offset = find(&buffer, b"\n1 0 obj") + 1;
+        let entry = find(&buffer, format!("{:010} 00000 n", offset).as_bytes());
+        let damaged = overwrite(buffer, entry, b"0000000003");
+        let loaded = Document::load_mem(&damaged);
+        assert!(loaded.map_or(true, |doc| doc.get_pages().is_empty()));
+
+        let (doc, report) = repair_document(&damaged).unwrap();
+        assert_eq!(doc.get_pages().len(), 3);
+        assert_eq!(report.objects_lost, 0);
+    }
+
+    #[test]
+    fn unparsable_objects_are_counted_as_lost() {
+        let buffer = sample_bytes(3);
+        // The text of the last page, whose content stream loses its dictionary
+        let stream = find(&buffer, b"(3) Tj");
+        let dictionary = find(&buffer[..stream], b"<<");
+        let damaged = overwrite(buffer, dictionary, b"))");
+
+        let (doc, report) = repair_document(&damaged).unwrap();
+        assert_eq!(doc.get_pages().len(), 3);
+        assert_eq!(report.objects_lost, 1);
+    }
+
+    #[test]
+    fn damaged_fixtures_are_repaired() {
+        for name in DAMAGED_FIXTURES {
+            let (doc, _) = repair_fixture(name).unwrap();
+            assert_eq!(doc.get_pages().len(), 2, "{}", name);
+        }
+    }
+
+    #[test]
+    fn repaired_copy_is_written_under_its_name_only() {
+        let folder = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        fs::create_dir_all(&folder).unwrap();
+        let output_path = folder.join("repaired.pdf");
+
+        repair_file(&fixture_path("truncated.pdf"), &output_path).unwrap();
+        assert_eq!(Document::load(&output_path).unwrap().get_pages().len(), 2);
+        // No temporary file is left next to it
+        assert_eq!(fs::read_dir(&folder).unwrap().count(), 1);
+        let _ = fs::remove_dir_all(&folder);
+    }
+
+    #[test]
+    fn missing_root_is_found_again() {
+        let (doc, report) = repair_fixture("missing_root.pdf").unwrap();
+        let catalog = doc.catalog().unwrap();
+        assert!(catalog.has_type(b"Catalog") && catalog.has(b"Pages"));
+        assert_eq!(report.objects_lost, 0);
+    }
+
+    #[test]
+    fn files_without_objects_cannot_be_repaired() {
+        let result = repair_document(b"not a pdf at all");
+        assert_eq!(result.err().unwrap().code, ErrorCode::PdfLoadError);
+    }
+}
@@ -0,0 +1,38 @@
+use std::sync::RwLock;
+
+use crate::upload::enums::OutputFormat;
+use crate::upload::structs::{ImageEncoding, RenderSettingsData};
+use crate::upload::sync::{read, write};
+
+/// How rendered pages are encoded, chosen separately for thumbnails and the full resolution view.
+pub struct RenderSettings {
+    settings: RwLock<RenderSettingsData>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            settings: RwLock::new(RenderSettingsData {
+                // Thumbnails are small and numerous, so size matters more than fidelity
+                thumbnails: ImageEncoding {
+                    format: OutputFormat::WebpLossy,
+                    quality: 85,
+                },
+                full_res: ImageEncoding {
+                    format: OutputFormat::WebpLossless,
+                    quality: 100,
+                },
+            }),
+        }
+    }
+}
+
+impl RenderSettings {
+    pub(crate) fn get(&self) -> RenderSettingsData {
+        *read(&self.settings)
+    }
+
+    pub(crate) fn set(&self, settings: RenderSettingsData) {
+        *write(&self.settings) = settings;
+    }
+}
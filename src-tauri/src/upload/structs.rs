@@ -1,9 +1,15 @@
-#[derive(serde::Serialize, serde::Deserialize)]
+use crate::upload::enums::{
+    InputOrder, OutputFormat, OverwritePolicy, PageBox, PageLayout, PaperSize, PdfFeature,
+    SourceState,
+};
+use crate::upload::error::CommandError;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct ThumbnailData {
     pub id: String, // uuid
     pub page_index: usize,
     pub file_path: String,
-    pub thumbnail: String, // base64 data URL
+    pub thumbnail: String, // ignis:// page url
     pub width: u32,
     pub height: u32,
 }
@@ -13,41 +19,268 @@ pub struct ThumbnailDownloadData {
     pub file_path: String,
     pub page_index: usize,
     pub password: Option<String>,
+    pub crop: Option<RegionRect>, // points on the page as displayed, becomes the CropBox
+}
+
+/// Optional page processing applied by `download_file`.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct ExportOptions {
+    // Sets the CropBox of every page to this box, e.g. Trim to cut off bleed and printer's marks
+    pub trim_to: Option<PageBox>,
+    // Scales every page to fit this paper size, centered; with a layout, the size of its sheets
+    pub page_size: Option<PaperSize>,
+    // Keeps the paper portrait for landscape pages instead of turning it to match them
+    pub fixed_orientation: bool,
+    // Places several pages on each sheet
+    pub layout: Option<PageLayout>,
+    // Adds a blank page after every document with an odd page count, for duplex printing
+    pub pad_to_even: bool,
+    // Puts pages listing every source document, with links to them, in front
+    pub table_of_contents: Option<TableOfContents>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct TableOfContents {
+    pub title: String,
+    // Also adds a bookmark for every source document
+    #[serde(default)]
+    pub outlines: bool,
+}
+
+/// Where `download_file` and `change_security` write. Without a directory or path, exports go
+/// to the Downloads folder.
+#[derive(serde::Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct OutputDestination {
+    pub directory: Option<String>,
+    // A full path chosen in the save dialog, used instead of the directory and file name
+    pub file_path: Option<String>,
+    pub overwrite: OverwritePolicy,
 }
 
 #[derive(serde::Serialize)]
 pub struct DocumentData {
     pub id: String, // uuid
     pub file_name: String,
-    pub file_path: String,
+    pub file_path: String, // what is rendered, a temporary PDF for converted files
+    pub source_path: String, // the file that was opened
     pub thumbnails: Vec<ThumbnailData>,
-    pub error: Option<String>,
+    pub page_count: usize,
+    pub permissions: Option<DocumentPermissions>,
+    pub repair: Option<RepairReport>, // set if the file was damaged and is shown repaired
+}
+
+#[derive(serde::Serialize, Clone, Copy, Debug)]
+pub struct RepairReport {
+    pub objects_lost: usize,
+}
+
+#[derive(serde::Serialize, Clone)]
+pub struct ThumbnailReadyEvent {
+    pub document_id: String,
+    pub thumbnail: ThumbnailData,
+    pub rendered: usize,
+    pub total: usize,
+}
+
+#[derive(serde::Serialize, Clone)]
+pub struct ThumbnailsFinishedEvent {
+    pub document_id: String,
+    pub cancelled: bool,
+    pub error: Option<CommandError>,
+}
+
+#[derive(serde::Serialize)]
+pub struct DocumentPermissions {
+    pub encrypted: bool,
+    pub can_print: bool,
+    pub can_modify: bool,
+    pub can_copy: bool,
+    pub can_annotate: bool,
+    pub can_fill_forms: bool,
+    pub can_assemble: bool,
 }
 
 #[derive(serde::Serialize)]
 pub struct FullImageData {
-    pub data_url: String,
+    pub url: String, // ignis:// page url
     pub width: u32,
     pub height: u32,
+    // Page size in points, the unit of render_region's rectangle
+    pub page_width: f32,
+    pub page_height: f32,
+}
+
+/// A rectangle on the page in points, from the top left corner of the page as displayed.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy)]
+pub struct RegionRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
 }
 
 #[derive(serde::Serialize)]
+pub struct RegionImageData {
+    pub url: String, // ignis:// page url
+    pub region: RegionRect, // the requested region, clamped to the page
+    pub scale: f32, // pixels per point actually rendered at
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(serde::Serialize, Clone)]
 pub struct DownloadSuccessRes {
     pub key: String,
     pub file_name: String,
-    pub file_path: String
+    pub file_path: String,
+    pub broken_references: Vec<BrokenReferences>, // sources exported with missing objects as null
 }
 
-#[derive(serde::Serialize)]
-pub struct CommandErrRes {
-    pub key: String,
+/// References of a source to objects missing from it, which the export replaced with null.
+#[derive(serde::Serialize, Clone)]
+pub struct BrokenReferences {
     pub file_name: String,
-    pub file_path: Option<String>,
-    pub page_index: Option<usize>
+    pub file_path: String,
+    pub count: usize,
 }
 
-#[derive(serde::Deserialize, PartialEq)]
+#[derive(serde::Serialize, serde::Deserialize, PartialEq, Debug, Clone, Copy)]
 pub enum EncryptionLevel {
     Aes128,
     Aes256,
-}
\ No newline at end of file
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy)]
+pub struct PermissionsData {
+    pub print: bool,
+    pub print_high_quality: bool,
+    pub modify: bool,
+    pub copy: bool,
+    pub annotate: bool,
+    pub fill_forms: bool,
+    pub assemble: bool,
+}
+
+#[derive(serde::Serialize, Clone)]
+pub struct JobProgress {
+    pub job_id: String,
+    pub done: usize,
+    pub total: usize,
+}
+#[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub struct ImageEncoding {
+    pub format: OutputFormat,
+    pub quality: u8, // 1-100, ignored by the lossless formats
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy)]
+pub struct RenderSettingsData {
+    pub thumbnails: ImageEncoding,
+    pub full_res: ImageEncoding,
+}
+
+/// The workspace as saved in a `.ignis` project file.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct ProjectData {
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default)]
+    pub sources: Vec<ProjectSource>,
+    pub documents: Vec<ProjectDocument>,
+    #[serde(default)]
+    pub export_options: ExportOptions,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct ProjectSource {
+    pub file_path: String,
+    // md5 of the content when the project was saved, None if it could not be read
+    #[serde(default)]
+    pub file_hash: Option<String>,
+    // Only whether a password is needed; passwords are never saved
+    #[serde(default)]
+    pub encrypted: bool,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct ProjectDocument {
+    pub file_name: String,
+    pub file_path: String,
+    // In order, with duplicated pages repeated and removed pages left out
+    pub pages: Vec<ProjectPage>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct ProjectPage {
+    pub file_path: String,
+    pub page_index: usize,
+    pub crop: Option<RegionRect>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ProjectSourceStatus {
+    pub file_path: String,
+    pub state: SourceState,
+}
+
+#[derive(serde::Serialize)]
+pub struct ProjectOpenRes {
+    pub project: ProjectData,
+    pub sources: Vec<ProjectSourceStatus>,
+}
+
+/// A saved merge: the files matching each input in turn, exported with the same settings every
+/// time it runs.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct MergeRecipe {
+    #[serde(default)]
+    pub id: String, // uuid, assigned when first saved
+    pub name: String,
+    pub file_name: String, // of the merged PDF
+    // Relative input patterns are resolved against this folder
+    pub folder: String,
+    pub inputs: Vec<RecipeInput>,
+    // The password is asked for on every run, never saved
+    #[serde(default)]
+    pub encryption_level: Option<EncryptionLevel>,
+    #[serde(default)]
+    pub export_options: ExportOptions,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct RecipeInput {
+    // A file, or a file name pattern with * and ? such as `scans/*.jpg`
+    pub pattern: String,
+    #[serde(default)]
+    pub order: InputOrder,
+}
+
+#[derive(serde::Serialize, Clone)]
+pub struct RecipeRunEvent {
+    pub recipe_id: String,
+    pub result: Option<DownloadSuccessRes>,
+    pub error: Option<CommandError>,
+}
+
+#[derive(serde::Serialize)]
+pub struct RecipeListRes {
+    pub recipes: Vec<MergeRecipe>,
+    pub watched: Vec<String>, // ids of the recipes being watched
+}
+
+/// What `preflight_export` found, before anything is written.
+#[derive(serde::Serialize)]
+pub struct PreflightReport {
+    pub issues: Vec<CommandError>, // every problem the export would fail on, empty if it can run
+    pub lost_features: Vec<LostFeatures>,
+    pub estimated_size: u64, // bytes, of the source pages before any page processing
+}
+
+#[derive(serde::Serialize)]
+pub struct LostFeatures {
+    pub file_name: String,
+    pub file_path: String,
+    pub features: Vec<PdfFeature>,
+}
@@ -0,0 +1,43 @@
+use std::sync::{Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+// A lock is poisoned when a thread panicked while holding it. The state behind the locks of this
+// module is only ever changed in single steps, so it is still whole, and a panic in one command
+// must not make every later command that shares it panic as well.
+
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub(crate) fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub(crate) fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn poisoned_locks_stay_usable() {
+        let mutex = Mutex::new(1);
+        let rw_lock = RwLock::new(1);
+        thread::scope(|scope| {
+            let _ = scope
+                .spawn(|| {
+                    let _guards = (mutex.lock().unwrap(), rw_lock.write().unwrap());
+                    panic!("poisons both locks");
+                })
+                .join();
+        });
+        assert!(mutex.is_poisoned() && rw_lock.is_poisoned());
+
+        *lock(&mutex) += 1;
+        *write(&rw_lock) += 1;
+        assert_eq!(*lock(&mutex), 2);
+        assert_eq!(*read(&rw_lock), 2);
+    }
+}
@@ -0,0 +1,708 @@
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+use rusttype::{Font, Scale};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use tauri::{AppHandle, Manager};
+
+use crate::upload::boxes::box_object;
+use crate::upload::enums::PageLayout;
+use crate::upload::error::ErrorCode;
+use crate::upload::layout::sheet_indices;
+
+// Sizes in points
+const MARGIN: f32 = 56.7; // 20mm, like text converted to PDF
+const HEADING_SIZE: f32 = 20.0;
+const ENTRY_SIZE: f32 = 11.0;
+const LINE_HEIGHT: f32 = 18.0;
+// Space between a title, its dot leader and its page number
+const LEADER_GAP: f32 = 6.0;
+
+// What readers need of an embedded TrueType font, in the order the tables are written
+const SUBSET_TABLES: [&[u8; 4]; 9] = [
+    b"cvt ", b"fpgm", b"glyf", b"head", b"hhea", b"hmtx", b"loca", b"maxp", b"prep",
+];
+
+/// A source document in the table of contents.
+pub(crate) struct TocEntry {
+    pub title: String,
+    // Position of its first page among the exported pages, before the table of contents
+    pub page_index: usize,
+    pub target: ObjectId,
+}
+
+/// DejaVu Sans, addressed by glyph id so any text it covers can be set, and embedded with the
+/// outlines of the glyphs used only.
+struct EmbeddedFont {
+    font: Font<'static>,
+    data: Vec<u8>,
+    // Glyph widths in thousandths of the font size, and the text each glyph stands for
+    used: BTreeMap<u16, (f32, char)>,
+}
+
+impl EmbeddedFont {
+    fn load(app: &AppHandle) -> Result<Self, ErrorCode> {
+        let path = app
+            .path()
+            .resource_dir()
+            .map_err(|_| ErrorCode::PdfTocFontError)?
+            .join("assets")
+            .join("fonts")
+            .join("DejaVuSans-Regular.ttf");
+        let data = fs::read(path).map_err(|_| ErrorCode::PdfTocFontError)?;
+        let font = Font::try_from_vec(data.clone()).ok_or(ErrorCode::PdfTocFontError)?;
+
+        Ok(EmbeddedFont {
+            font,
+            data,
+            used: BTreeMap::new(),
+        })
+    }
+
+    // rusttype scales to the height from descender to ascender, not to the em square
+    fn thousandths_scale(&self) -> Scale {
+        let metrics = self.font.v_metrics_unscaled();
+        let height = metrics.ascent - metrics.descent;
+        Scale::uniform(1000.0 * height / f32::from(self.font.units_per_em()))
+    }
+
+    fn glyph_width(&self, c: char) -> f32 {
+        self.font
+            .glyph(c)
+            .scaled(self.thousandths_scale())
+            .h_metrics()
+            .advance_width
+    }
+
+    fn width(&self, text: &str, size: f32) -> f32 {
+        text.chars().map(|c| self.glyph_width(c)).sum::<f32>() * size / 1000.0
+    }
+
+    /// Glyph ids of the text as a hex string for `Tj`, remembering the glyphs used.
+    fn encode(&mut self, text: &str) -> String {
+        let mut encoded = String::from("<");
+        for c in text.chars() {
+            let id = self.font.glyph(c).id().0;
+            let width = self.glyph_width(c);
+            self.used.entry(id).or_insert((width, c));
+            encoded.push_str(&format!("{:04X}", id));
+        }
+        encoded.push('>');
+        encoded
+    }
+
+    /// Shortens the text with an ellipsis until it fits into `max_width`.
+    fn fit(&self, text: &str, size: f32, max_width: f32) -> String {
+        if self.width(text, size) <= max_width {
+            return text.to_string();
+        }
+
+        let mut chars: Vec<char> = text.chars().collect();
+        while !chars.is_empty() {
+            chars.pop();
+            let shortened = format!("{}…", chars.iter().collect::<String>().trim_end());
+            if self.width(&shortened, size) <= max_width {
+                return shortened;
+            }
+        }
+        "…".to_string()
+    }
+
+    fn to_unicode_cmap(&self) -> Vec<u8> {
+        let mut cmap = String::from(
+            "/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+             /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+             /CMapName /Adobe-Identity-UCS def\n/CMapType 2 def\n\
+             1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n",
+        );
+
+        let used: Vec<_> = self.used.iter().collect();
+        // At most 100 mappings per block
+        for block in used.chunks(100) {
+            cmap.push_str(&format!("{} beginbfchar\n", block.len()));
+            for (id, (_, c)) in block {
+                let unicode: String = c
+                    .encode_utf16(&mut [0; 2])
+                    .iter()
+                    .map(|unit| format!("{:04X}", unit))
+                    .collect();
+                cmap.push_str(&format!("<{:04X}> <{}>\n", id, unicode));
+            }
+            cmap.push_str("endbfchar\n");
+        }
+
+        cmap.push_str("endcmap\nCMapName currentdict /CMap defineresource pop\nend\nend\n");
+        cmap.into_bytes()
+    }
+
+    /// Writes the font objects and returns the id of the font dictionary.
+    fn write(self, doc: &mut Document) -> ObjectId {
+        let metrics = self.font.v_metrics_unscaled();
+        let to_thousandths = 1000.0 / f32::from(self.font.units_per_em());
+        let to_unicode = self.to_unicode_cmap();
+
+        let mut widths = Vec::new();
+        for (id, (width, _)) in &self.used {
+            widths.push(Object::Integer(i64::from(*id)));
+            widths.push(Object::Array(vec![Object::Real(*width)]));
+        }
+
+        // Embedding the whole font only costs file size
+        let (base_font, data) = match subset_font(&self.data, self.used.keys().copied()) {
+            Some((tag, data)) => (format!("{}+DejaVuSans", tag), data),
+            None => ("DejaVuSans".to_string(), self.data),
+        };
+
+        let mut font_file_dict = Dictionary::new();
+        font_file_dict.set("Length1", Object::Integer(data.len() as i64));
+        let mut font_file = Stream::new(font_file_dict, data);
+        // Not compressing only costs file size
+        let _ = font_file.compress();
+        let font_file_id = doc.add_object(font_file);
+
+        let mut descriptor = Dictionary::new();
+        descriptor.set("Type", Object::Name(b"FontDescriptor".to_vec()));
+        descriptor.set("FontName", Object::Name(base_font.clone().into_bytes()));
+        descriptor.set("Flags", Object::Integer(32)); // Nonsymbolic
+        // Of DejaVu Sans, in thousandths of the font size
+        descriptor.set("FontBBox", box_object([-1021.0, -463.0, 1793.0, 1232.0]));
+        descriptor.set("ItalicAngle", Object::Integer(0));
+        descriptor.set("Ascent", Object::Real(metrics.ascent * to_thousandths));
+        descriptor.set("Descent", Object::Real(metrics.descent * to_thousandths));
+        descriptor.set("CapHeight", Object::Integer(729));
+        descriptor.set("StemV", Object::Integer(80));
+        descriptor.set("FontFile2", Object::Reference(font_file_id));
+        let descriptor_id = doc.add_object(descriptor);
+
+        let mut system_info = Dictionary::new();
+        system_info.set("Registry", Object::string_literal("Adobe"));
+        system_info.set("Ordering", Object::string_literal("Identity"));
+        system_info.set("Supplement", Object::Integer(0));
+
+        let mut cid_font = Dictionary::new();
+        cid_font.set("Type", Object::Name(b"Font".to_vec()));
+        cid_font.set("Subtype", Object::Name(b"CIDFontType2".to_vec()));
+        cid_font.set("BaseFont", Object::Name(base_font.clone().into_bytes()));
+        cid_font.set("CIDSystemInfo", Object::Dictionary(system_info));
+        cid_font.set("FontDescriptor", Object::Reference(descriptor_id));
+        cid_font.set("DW", Object::Integer(1000));
+        cid_font.set("W", Object::Array(widths));
+        cid_font.set("CIDToGIDMap", Object::Name(b"Identity".to_vec()));
+        let cid_font_id = doc.add_object(cid_font);
+
+        let to_unicode_id = doc.add_object(Stream::new(Dictionary::new(), to_unicode));
+
+        let mut font = Dictionary::new();
+        font.set("Type", Object::Name(b"Font".to_vec()));
+        font.set("Subtype", Object::Name(b"Type0".to_vec()));
+        font.set("BaseFont", Object::Name(base_font.into_bytes()));
+        font.set("Encoding", Object::Name(b"Identity-H".to_vec()));
+        font.set(
+            "DescendantFonts",
+            Object::Array(vec![Object::Reference(cid_font_id)]),
+        );
+        font.set("ToUnicode", Object::Reference(to_unicode_id));
+        doc.add_object(font)
+    }
+}
+
+fn read_u16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+// Sum of the big endian words of a table, zero padded to whole words
+fn table_checksum(table: &[u8]) -> u32 {
+    table.chunks(4).fold(0u32, |sum, word| {
+        let mut padded = [0; 4];
+        padded[..word.len()].copy_from_slice(word);
+        sum.wrapping_add(u32::from_be_bytes(padded))
+    })
+}
+
+/// The glyphs a composite glyph is assembled from.
+fn glyph_components(glyph: &[u8]) -> Option<Vec<u16>> {
+    const ARGS_ARE_WORDS: u16 = 0x0001;
+    const HAS_SCALE: u16 = 0x0008;
+    const MORE_COMPONENTS: u16 = 0x0020;
+    const HAS_X_AND_Y_SCALE: u16 = 0x0040;
+    const HAS_TWO_BY_TWO: u16 = 0x0080;
+
+    let mut components = Vec::new();
+    // Simple glyphs, and empty ones, have a contour count of zero or more
+    if glyph.is_empty() || (read_u16(glyph, 0)? as i16) >= 0 {
+        return Some(components);
+    }
+
+    let mut at = 10;
+    loop {
+        let flags = read_u16(glyph, at)?;
+        components.push(read_u16(glyph, at + 2)?);
+        at += 4 + if flags & ARGS_ARE_WORDS != 0 { 4 } else { 2 };
+        at += if flags & HAS_SCALE != 0 {
+            2
+        } else if flags & HAS_X_AND_Y_SCALE != 0 {
+            4
+        } else if flags & HAS_TWO_BY_TWO != 0 {
+            8
+        } else {
+            0
+        };
+        if flags & MORE_COMPONENTS == 0 {
+            return Some(components);
+        }
+    }
+}
+
+/// The tables of a TrueType font by tag. `None` if the directory points outside the file.
+fn font_tables(data: &[u8]) -> Option<BTreeMap<[u8; 4], &[u8]>> {
+    let mut tables = BTreeMap::new();
+    for record in 0..usize::from(read_u16(data, 4)?) {
+        let at = 12 + 16 * record;
+        let tag: [u8; 4] = data.get(at..at + 4)?.try_into().ok()?;
+        let offset = read_u32(data, at + 8)? as usize;
+        let length = read_u32(data, at + 12)? as usize;
+        tables.insert(tag, data.get(offset..offset.checked_add(length)?)?);
+    }
+    Some(tables)
+}
+
+/// The outline of a glyph in `glyf`, found by its offsets in `loca`: 32 bit ones if
+/// `long_offsets`, else 16 bit ones counting words. Empty for a glyph without an outline.
+fn glyph_outline<'a>(glyf: &'a [u8], loca: &[u8], long_offsets: bool, id: u16) -> Option<&'a [u8]> {
+    let id = usize::from(id);
+    let (start, end) = if long_offsets {
+        (
+            read_u32(loca, 4 * id)? as usize,
+            read_u32(loca, 4 * id + 4)? as usize,
+        )
+    } else {
+        (
+            2 * usize::from(read_u16(loca, 2 * id)?),
+            2 * usize::from(read_u16(loca, 2 * id + 2)?),
+        )
+    };
+    glyf.get(start..end)
+}
+
+/// Strips the TrueType font down to the outlines of the `used` glyphs and the tables a PDF needs,
+/// returning it with the tag that names the subset. Glyph ids stay as they are, so text encoded
+/// for the whole font shows the same. `None` if the font is not laid out as expected.
+fn subset_font(data: &[u8], used: impl IntoIterator<Item = u16>) -> Option<(String, Vec<u8>)> {
+    let tables = font_tables(data)?;
+    let head = *tables.get(b"head")?;
+    let glyf = *tables.get(b"glyf")?;
+    let loca = *tables.get(b"loca")?;
+    let glyph_count = read_u16(tables.get(b"maxp")?, 4)?;
+    let long_offsets = read_u16(head, 50)? != 0;
+    let glyph = |id: u16| glyph_outline(glyf, loca, long_offsets, id);
+
+    // Glyph 0 is shown for anything missing, and composites need their parts
+    let mut kept = BTreeSet::new();
+    let mut pending: Vec<u16> = used.into_iter().chain([0]).collect();
+    while let Some(id) = pending.pop() {
+        if id < glyph_count && kept.insert(id) {
+            pending.extend(glyph_components(glyph(id)?)?);
+        }
+    }
+
+    let mut new_glyf = Vec::new();
+    let mut new_loca = Vec::new();
+    for id in 0..glyph_count {
+        new_loca.extend((new_glyf.len() as u32).to_be_bytes());
+        if kept.contains(&id) {
+            new_glyf.extend_from_slice(glyph(id)?);
+            new_glyf.resize(new_glyf.len().next_multiple_of(4), 0);
+        }
+    }
+    new_loca.extend((new_glyf.len() as u32).to_be_bytes());
+
+    let mut new_head = head.to_vec();
+    // The checksum adjustment is set once the file is complete, and loca is now in long offsets
+    new_head.get_mut(8..12)?.fill(0);
+    new_head
+        .get_mut(50..52)?
+        .copy_from_slice(&1u16.to_be_bytes());
+
+    let subset: Vec<(&[u8; 4], &[u8])> = SUBSET_TABLES
+        .into_iter()
+        .filter_map(|tag| match tag {
+            b"glyf" => Some((tag, new_glyf.as_slice())),
+            b"head" => Some((tag, new_head.as_slice())),
+            b"loca" => Some((tag, new_loca.as_slice())),
+            _ => tables.get(tag).map(|table| (tag, *table)),
+        })
+        .collect();
+
+    let table_count = subset.len() as u16;
+    let entry_selector = table_count.ilog2() as u16;
+    let search_range = (1 << entry_selector) * 16;
+    let mut font = Vec::new();
+    font.extend(0x0001_0000u32.to_be_bytes());
+    for value in [
+        table_count,
+        search_range,
+        entry_selector,
+        table_count * 16 - search_range,
+    ] {
+        font.extend(value.to_be_bytes());
+    }
+
+    let mut offset = 12 + 16 * subset.len();
+    let mut head_offset = 0;
+    for (tag, table) in &subset {
+        if *tag == b"head" {
+            head_offset = offset;
+        }
+        font.extend_from_slice(*tag);
+        font.extend(table_checksum(table).to_be_bytes());
+        font.extend((offset as u32).to_be_bytes());
+        font.extend((table.len() as u32).to_be_bytes());
+        offset += table.len().next_multiple_of(4);
+    }
+    for (_, table) in &subset {
+        font.extend_from_slice(table);
+        font.resize(font.len().next_multiple_of(4), 0);
+    }
+
+    let adjustment = 0xB1B0_AFBAu32.wrapping_sub(table_checksum(&font));
+    font.get_mut(head_offset + 8..head_offset + 12)?
+        .copy_from_slice(&adjustment.to_be_bytes());
+
+    // Six capital letters that tell this subset apart from others of the font
+    let mut seed = kept.iter().fold(0u32, |hash, &id| {
+        hash.wrapping_mul(31).wrapping_add(u32::from(id))
+    });
+    let tag = (0..6)
+        .map(|_| {
+            let letter = char::from(b'A' + (seed % 26) as u8);
+            seed /= 26;
+            letter
+        })
+        .collect();
+
+    Some((tag, font))
+}
+
+fn show_text(
+    content: &mut String,
+    font: &mut EmbeddedFont,
+    text: &str,
+    size: f32,
+    (x, y): (f32, f32),
+) {
+    let encoded = font.encode(text);
+    content.push_str(&format!("BT /F1 {} Tf {} {} Td {} Tj ET\n", size, x, y, encoded));
+}
+
+fn link(rect: [f32; 4], target: ObjectId) -> Dictionary {
+    let mut link = Dictionary::new();
+    link.set("Type", Object::Name(b"Annot".to_vec()));
+    link.set("Subtype", Object::Name(b"Link".to_vec()));
+    link.set("Rect", box_object(rect));
+    link.set("Border", Object::Array(vec![0.into(), 0.into(), 0.into()]));
+    link.set(
+        "Dest",
+        Object::Array(vec![Object::Reference(target), Object::Name(b"Fit".to_vec())]),
+    );
+    link
+}
+
+/// Adds `width` x `height` pages under `parent_id` listing each entry with the number its first
+/// page will have once these pages are put in front of the `exported_pages` others, linked to that
+/// page. With a `layout`, that is the number of the sheet the page is placed on. Entries that do
+/// not fit on a page continue on the next one.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn add_toc_pages(
+    app: &AppHandle,
+    doc: &mut Document,
+    parent_id: ObjectId,
+    title: &str,
+    entries: &[TocEntry],
+    exported_pages: usize,
+    layout: Option<PageLayout>,
+    (width, height): (f32, f32),
+) -> Result<Vec<ObjectId>, ErrorCode> {
+    let mut font = EmbeddedFont::load(app)?;
+
+    // Baselines of the entries, the first page starting below the heading
+    let first_page_top = height - MARGIN - HEADING_SIZE - 2.0 * LINE_HEIGHT;
+    let other_page_top = height - MARGIN - ENTRY_SIZE;
+    let lines_per_page = |top: f32| (((top - MARGIN) / LINE_HEIGHT).floor() as usize + 1).max(1);
+    let first_page_lines = lines_per_page(first_page_top);
+    let other_page_lines = lines_per_page(other_page_top);
+    let page_count = 1 + entries
+        .len()
+        .saturating_sub(first_page_lines)
+        .div_ceil(other_page_lines);
+
+    let sheets = layout.map(|layout| sheet_indices(layout, page_count + exported_pages));
+    let page_number = |entry: &TocEntry| {
+        let position = page_count + entry.page_index;
+        sheets.as_ref().map_or(position, |sheets| sheets[position]) + 1
+    };
+
+    let mut pages: Vec<(String, Vec<Dictionary>)> = Vec::new();
+    let mut remaining = entries.iter();
+    for page_index in 0..page_count {
+        let mut content = String::new();
+        let (top, lines) = if page_index == 0 {
+            let heading = font.fit(title, HEADING_SIZE, width - 2.0 * MARGIN);
+            let position = (MARGIN, height - MARGIN - HEADING_SIZE);
+            show_text(&mut content, &mut font, &heading, HEADING_SIZE, position);
+            (first_page_top, first_page_lines)
+        } else {
+            (other_page_top, other_page_lines)
+        };
+
+        let mut links = Vec::new();
+        for (line, entry) in remaining.by_ref().take(lines).enumerate() {
+            let y = top - line as f32 * LINE_HEIGHT;
+            let number = page_number(entry).to_string();
+            let number_width = font.width(&number, ENTRY_SIZE);
+            let max_title_width = width - 2.0 * MARGIN - number_width - 2.0 * LEADER_GAP;
+            let entry_title = font.fit(&entry.title, ENTRY_SIZE, max_title_width);
+            let title_width = font.width(&entry_title, ENTRY_SIZE);
+            let number_x = width - MARGIN - number_width;
+
+            show_text(&mut content, &mut font, &entry_title, ENTRY_SIZE, (MARGIN, y));
+            show_text(&mut content, &mut font, &number, ENTRY_SIZE, (number_x, y));
+
+            // Dots from the title to the page number, flush against the number
+            let leader_width = number_x - LEADER_GAP - (MARGIN + title_width + LEADER_GAP);
+            let dots = (leader_width / font.width(".", ENTRY_SIZE)).floor() as usize;
+            if dots > 0 {
+                let leader = ".".repeat(dots);
+                let leader_x = number_x - LEADER_GAP - font.width(&leader, ENTRY_SIZE);
+                show_text(&mut content, &mut font, &leader, ENTRY_SIZE, (leader_x, y));
+            }
+
+            let rect = [MARGIN, y - 4.0, width - MARGIN, y + ENTRY_SIZE];
+            links.push(link(rect, entry.target));
+        }
+        pages.push((content, links));
+    }
+
+    // All text is set, so the font knows every glyph it needs
+    let font_id = font.write(doc);
+    let mut fonts = Dictionary::new();
+    fonts.set("F1", Object::Reference(font_id));
+    let mut resources = Dictionary::new();
+    resources.set("Font", Object::Dictionary(fonts));
+    let resources_id = doc.add_object(resources);
+
+    let mut page_ids = Vec::new();
+    for (content, links) in pages {
+        let mut stream = Stream::new(Dictionary::new(), content.into_bytes());
+        let _ = stream.compress();
+        let content_id = doc.add_object(stream);
+
+        let page_id = doc.new_object_id();
+        let annotations = links
+            .into_iter()
+            .map(|mut link| {
+                link.set("P", Object::Reference(page_id));
+                Object::Reference(doc.add_object(link))
+            })
+            .collect();
+
+        let mut page = Dictionary::new();
+        page.set("Type", Object::Name(b"Page".to_vec()));
+        page.set("Parent", Object::Reference(parent_id));
+        page.set("MediaBox", box_object([0.0, 0.0, width, height]));
+        page.set("Resources", Object::Reference(resources_id));
+        page.set("Contents", Object::Reference(content_id));
+        page.set("Annots", Object::Array(annotations));
+        doc.objects.insert(page_id, Object::Dictionary(page));
+        page_ids.push(page_id);
+    }
+
+    Ok(page_ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusttype::{point, GlyphId};
+
+    fn dejavu() -> Vec<u8> {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("assets")
+            .join("fonts")
+            .join("DejaVuSans-Regular.ttf");
+        fs::read(path).expect("font is bundled")
+    }
+
+    // The pixel bounds of a glyph, `None` for one without an outline
+    fn bounds(font: &Font, id: GlyphId) -> Option<rusttype::Rect<i32>> {
+        font.glyph(id)
+            .scaled(Scale::uniform(100.0))
+            .positioned(point(0.0, 0.0))
+            .pixel_bounding_box()
+    }
+
+    #[test]
+    fn subset_keeps_the_outlines_of_used_glyphs_only() {
+        let data = dejavu();
+        let font = Font::try_from_vec(data.clone()).unwrap();
+        let text = "Contents 12…é";
+        let (tag, subset) = subset_font(&data, text.chars().map(|c| font.glyph(c).id().0)).unwrap();
+
+        assert!(tag.len() == 6 && tag.chars().all(|c| c.is_ascii_uppercase()));
+        assert!(subset.len() < data.len() / 10);
+        // Whole file checksum as the font format requires
+        assert_eq!(table_checksum(&subset), 0xB1B0_AFBA);
+
+        // The subset has no character map, so glyphs are looked up by id
+        let subset_font = Font::try_from_vec(subset).unwrap();
+        for c in text.chars().filter(|c| !c.is_whitespace()) {
+            let id = font.glyph(c).id();
+            assert!(bounds(&subset_font, id).is_some());
+            assert_eq!(bounds(&subset_font, id), bounds(&font, id));
+        }
+        assert!(bounds(&subset_font, font.glyph('Z').id()).is_none());
+    }
+
+    #[test]
+    fn subset_of_other_data_is_none() {
+        assert!(subset_font(b"not a font", [1, 2, 3]).is_none());
+    }
+
+    // A simple glyph: one contour, a bounding box of `n`s and no points. Test glyphs are whole
+    // words long, as the subset pads them to that.
+    fn simple(n: u8) -> Vec<u8> {
+        let mut glyph = vec![0, 1];
+        glyph.extend([n; 8]);
+        glyph.extend([0, 0]);
+        glyph
+    }
+
+    // A composite glyph of these glyphs, placed by word offsets, the last one scaled
+    fn composite(components: &[u16]) -> Vec<u8> {
+        const ARGS_ARE_WORDS: u16 = 0x0001;
+        const HAS_SCALE: u16 = 0x0008;
+        const MORE_COMPONENTS: u16 = 0x0020;
+
+        let mut glyph = vec![0xFF, 0xFF];
+        glyph.extend([0; 8]);
+        for (index, id) in components.iter().enumerate() {
+            let last = index + 1 == components.len();
+            let flags = ARGS_ARE_WORDS | if last { HAS_SCALE } else { MORE_COMPONENTS };
+            glyph.extend(flags.to_be_bytes());
+            glyph.extend(id.to_be_bytes());
+            glyph.extend([0; 4]);
+        }
+        // A scale of 1.0
+        glyph.extend([0x40, 0]);
+        glyph
+    }
+
+    // The tables of a font of these glyphs, as far as `subset_font` reads them
+    fn glyph_tables(glyphs: &[Vec<u8>], long_offsets: bool) -> Vec<([u8; 4], Vec<u8>)> {
+        let mut head = vec![0; 54];
+        head[50..52].copy_from_slice(&u16::from(long_offsets).to_be_bytes());
+        let mut maxp = vec![0, 0, 0x50, 0];
+        maxp.extend((glyphs.len() as u16).to_be_bytes());
+
+        let mut glyf = Vec::new();
+        let mut loca = Vec::new();
+        for glyph in glyphs.iter().map(Vec::as_slice).chain([&[][..]]) {
+            if long_offsets {
+                loca.extend((glyf.len() as u32).to_be_bytes());
+            } else {
+                loca.extend((glyf.len() as u16 / 2).to_be_bytes());
+            }
+            glyf.extend_from_slice(glyph);
+        }
+        vec![
+            (*b"glyf", glyf),
+            (*b"head", head),
+            (*b"loca", loca),
+            (*b"maxp", maxp),
+        ]
+    }
+
+    fn font_file(tables: &[([u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut font = 0x0001_0000u32.to_be_bytes().to_vec();
+        font.extend((tables.len() as u16).to_be_bytes());
+        font.extend([0; 6]);
+        let mut offset = 12 + 16 * tables.len();
+        for (tag, table) in tables {
+            font.extend(tag);
+            font.extend([0; 4]);
+            font.extend((offset as u32).to_be_bytes());
+            font.extend((table.len() as u32).to_be_bytes());
+            offset += table.len();
+        }
+        for (_, table) in tables {
+            font.extend(table);
+        }
+        font
+    }
+
+    // The outlines of every glyph of a subset, which always has long offsets
+    fn subset_glyphs(glyphs: &[Vec<u8>], long_offsets: bool, used: &[u16]) -> Vec<Vec<u8>> {
+        let font = font_file(&glyph_tables(glyphs, long_offsets));
+        let (_, subset) = subset_font(&font, used.iter().copied()).unwrap();
+        let tables = font_tables(&subset).unwrap();
+        assert_eq!(read_u16(tables[b"head"], 50), Some(1));
+        let (glyf, loca) = (tables[b"glyf"], tables[b"loca"]);
+        (0..glyphs.len() as u16)
+            .map(|id| glyph_outline(glyf, loca, true, id).unwrap().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn short_offsets_are_read() {
+        let glyphs = [simple(0), simple(1), simple(2)];
+        let subset = subset_glyphs(&glyphs, false, &[2]);
+        assert_eq!(subset, [simple(0), vec![], simple(2)]);
+    }
+
+    #[test]
+    fn composite_glyphs_keep_their_components() {
+        // Glyph 2 also lists itself, which must not send the subset in circles
+        let glyphs = [simple(0), simple(1), composite(&[1, 2]), simple(3)];
+        let subset = subset_glyphs(&glyphs, true, &[2]);
+        assert_eq!(subset, [simple(0), simple(1), composite(&[1, 2]), vec![]]);
+
+        // Parts that do not exist are left out instead of failing the subset
+        let glyphs = [simple(0), composite(&[1, 7])];
+        assert_eq!(subset_glyphs(&glyphs, true, &[1]), glyphs);
+    }
+
+    #[test]
+    fn empty_glyphs_stay_empty() {
+        let glyphs = [simple(0), vec![], simple(2)];
+        assert_eq!(subset_glyphs(&glyphs, false, &[1, 2]), glyphs);
+    }
+
+    #[test]
+    fn damaged_glyph_tables_are_none() {
+        let glyphs = [simple(0), composite(&[0, 1]), simple(2)];
+        let subset = |tables: &[([u8; 4], Vec<u8>)]| subset_font(&font_file(tables), [1, 2]);
+        assert!(subset(&glyph_tables(&glyphs, true)).is_some());
+
+        // `loca` ends before the offsets of the last glyph
+        let mut tables = glyph_tables(&glyphs, true);
+        tables[2].1.truncate(12);
+        assert!(subset(&tables).is_none());
+
+        // A glyph that ends before it starts
+        let mut tables = glyph_tables(&glyphs, false);
+        tables[2].1[4..6].copy_from_slice(&1u16.to_be_bytes());
+        assert!(subset(&tables).is_none());
+
+        // A composite glyph cut off in its components
+        let mut tables = glyph_tables(&[simple(0), composite(&[0, 1])], true);
+        tables[2].1[8..12].copy_from_slice(&18u32.to_be_bytes());
+        assert!(subset(&tables).is_none());
+
+        // A table that runs past the end of the file
+        let mut font = font_file(&glyph_tables(&glyphs, true));
+        font.truncate(font.len() - 1);
+        assert!(subset_font(&font, [1]).is_none());
+    }
+}
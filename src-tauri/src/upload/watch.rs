@@ -0,0 +1,145 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::upload::commands::run_recipe_job;
+use crate::upload::enums::OverwritePolicy;
+use crate::upload::jobs::JobManager;
+use crate::upload::recipe::{resolve_recipe, RecipeStore};
+use crate::upload::structs::{MergeRecipe, RecipeRunEvent};
+use crate::upload::sync::lock;
+
+// Often enough to feel automatic, rarely enough that listing a large folder stays cheap
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+// The inputs of a recipe as seen on disk, None while they cannot be resolved
+type InputSnapshot = Option<Vec<(PathBuf, Option<SystemTime>, u64)>>;
+
+/// Recipes that re-run whenever their inputs change, each polled on its own thread.
+#[derive(Default)]
+pub struct RecipeWatcher {
+    watches: Mutex<HashMap<String, Arc<AtomicBool>>>,
+}
+
+impl RecipeWatcher {
+    /// Starts watching the recipe, replacing an earlier watch of it. The current inputs are taken
+    /// as they are; only changes from here on trigger a run.
+    pub(crate) fn watch(&self, app: &AppHandle, recipe: &MergeRecipe, password: Option<String>) {
+        let stopped = Arc::new(AtomicBool::new(false));
+        if let Some(previous) = lock(&self.watches).insert(recipe.id.clone(), stopped.clone()) {
+            previous.store(true, Ordering::Relaxed);
+        }
+
+        let app = app.clone();
+        let recipe_id = recipe.id.clone();
+        thread::spawn(move || watch_recipe_inputs(&app, &recipe_id, password, &stopped));
+    }
+
+    /// Returns `false` if the recipe was not being watched.
+    pub(crate) fn unwatch(&self, recipe_id: &str) -> bool {
+        match lock(&self.watches).remove(recipe_id) {
+            Some(stopped) => {
+                stopped.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn watched(&self) -> Vec<String> {
+        lock(&self.watches).keys().cloned().collect()
+    }
+}
+
+fn snapshot(recipe: &MergeRecipe, exclude: &[PathBuf]) -> InputSnapshot {
+    let files = resolve_recipe(recipe, exclude).ok()?;
+    Some(
+        files
+            .into_iter()
+            .map(|file| {
+                let metadata = fs::metadata(&file).ok();
+                let modified = metadata.as_ref().and_then(|m| m.modified().ok());
+                let len = metadata.map_or(0, |m| m.len());
+                (file, modified, len)
+            })
+            .collect(),
+    )
+}
+
+fn watch_recipe_inputs(
+    app: &AppHandle,
+    recipe_id: &str,
+    password: Option<String>,
+    stopped: &AtomicBool,
+) {
+    // Outputs written by this watch, which must not count as inputs if they land in the folder
+    let mut outputs: Vec<PathBuf> = Vec::new();
+    let mut last_run: InputSnapshot = match app.state::<RecipeStore>().get(recipe_id) {
+        Some(recipe) => snapshot(&recipe, &outputs),
+        None => return,
+    };
+    let mut pending: InputSnapshot = None;
+
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        if stopped.load(Ordering::Relaxed) {
+            return;
+        }
+
+        // Read on every poll, so edits to the recipe apply to the next run
+        let Some(recipe) = app.state::<RecipeStore>().get(recipe_id) else {
+            return;
+        };
+        let current = snapshot(&recipe, &outputs);
+
+        if current != last_run {
+            // Run once nothing changed for a whole interval, so files still being copied in are
+            // not merged half written
+            if current.is_some() && current == pending {
+                let jobs = app.state::<JobManager>();
+                let job = jobs.start(Some(format!("recipe-{}", recipe_id)));
+                // Each run replaces the output of the one before instead of piling up copies
+                let result = run_recipe_job(
+                    app,
+                    &job,
+                    &recipe,
+                    password.clone(),
+                    &outputs,
+                    OverwritePolicy::Overwrite,
+                );
+                jobs.finish(&job);
+
+                if let Ok(res) = &result {
+                    let output = PathBuf::from(&res.file_path);
+                    if !outputs.contains(&output) {
+                        outputs.push(output);
+                    }
+                }
+                let _ = app.emit(
+                    "recipe-run",
+                    RecipeRunEvent {
+                        recipe_id: recipe_id.to_string(),
+                        result: result.as_ref().ok().cloned(),
+                        error: result.err(),
+                    },
+                );
+
+                last_run = current;
+                pending = None;
+            } else {
+                pending = current;
+            }
+        } else {
+            pending = None;
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+use tauri::{AppHandle, Manager};
+use uuid::Uuid;
+
+use crate::upload::cache::hash_file;
+use crate::upload::error::CommandError;
+use crate::upload::structs::RepairReport;
+use crate::upload::sync::lock;
+
+/// Temporary PDFs made from the files the user opens: text and images converted to PDF, and
+/// repaired copies of damaged PDFs. They live in a folder of their own in the app cache directory,
+/// named by the content hash of their source, so a source that changed is converted again instead
+/// of showing a stale copy. A file is removed once no open document uses it, and the whole folder
+/// when the app exits, or the next time it starts if it did not exit cleanly.
+pub struct TempWorkspace {
+    dir: PathBuf,
+    // document id -> the temporary files it renders from
+    owners: Mutex<HashMap<String, HashSet<PathBuf>>>,
+    // source path -> (modified, length, content hash), so unchanged sources are not read again
+    hashes: Mutex<HashMap<PathBuf, (SystemTime, u64, String)>>,
+    // content hash -> what repairing the source lost
+    repairs: Mutex<HashMap<String, RepairReport>>,
+}
+
+impl TempWorkspace {
+    pub(crate) fn new(app: &AppHandle) -> Result<Self, String> {
+        let dir = app
+            .path()
+            .app_cache_dir()
+            .map_err(|e| format!("Could not get cache directory: {}", e))?
+            .join("workspace");
+
+        // Left over from a session that did not exit cleanly
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("Could not create workspace directory: {}", e))?;
+
+        Ok(Self {
+            dir,
+            owners: Mutex::default(),
+            hashes: Mutex::default(),
+            repairs: Mutex::default(),
+        })
+    }
+
+    /// The content hash of a source file, read again only when its modification time or length
+    /// changed since it was last hashed.
+    pub(crate) fn source_hash(&self, source: &Path) -> Result<String, CommandError> {
+        let stamp = fs::metadata(source)
+            .ok()
+            .and_then(|metadata| Some((metadata.modified().ok()?, metadata.len())));
+
+        let known = lock(&self.hashes).get(source).cloned();
+        if let (Some((modified, len)), Some((known_modified, known_len, hash))) = (stamp, known) {
+            if modified == known_modified && len == known_len {
+                return Ok(hash);
+            }
+        }
+
+        let hash = hash_file(source)?;
+        if let Some((modified, len)) = stamp {
+            lock(&self.hashes).insert(source.to_path_buf(), (modified, len, hash.clone()));
+        }
+        Ok(hash)
+    }
+
+    /// Where a file made from the source with this content hash goes. `kind` tells apart what is
+    /// made from the same content, e.g. `text` or `repaired`.
+    pub(crate) fn converted_path(&self, source_hash: &str, kind: &str) -> PathBuf {
+        self.dir.join(format!("{}_{}.pdf", source_hash, kind))
+    }
+
+    /// A file of its own, for conversions only the caller uses and removes afterwards.
+    pub(crate) fn scratch_path(&self) -> PathBuf {
+        self.dir.join(format!("{}.pdf", Uuid::new_v4()))
+    }
+
+    /// The conversion of the source with this content hash, made by `convert` unless an earlier
+    /// one can be reused. It is written under a scratch name and then renamed, so a conversion
+    /// that fails or is still being written is never mistaken for a finished one.
+    pub(crate) fn convert(
+        &self,
+        source_hash: &str,
+        kind: &str,
+        convert: impl FnOnce(&Path) -> Result<(), CommandError>,
+    ) -> Result<PathBuf, CommandError> {
+        let converted_path = self.converted_path(source_hash, kind);
+        if converted_path.exists() {
+            return Ok(converted_path);
+        }
+
+        let scratch_path = self.scratch_path();
+        if let Err(e) = convert(&scratch_path) {
+            let _ = fs::remove_file(&scratch_path);
+            return Err(e);
+        }
+        // Only fails if another document converted the same content meanwhile and holds it open,
+        // in which case that conversion is used
+        if fs::rename(&scratch_path, &converted_path).is_err() {
+            let _ = fs::remove_file(&scratch_path);
+        }
+        Ok(converted_path)
+    }
+
+    /// The repaired copy of the source with this content hash and what repairing it lost, made by
+    /// `repair` unless an earlier one can be reused.
+    pub(crate) fn repair(
+        &self,
+        source_hash: &str,
+        repair: impl FnOnce(&Path) -> Result<RepairReport, CommandError>,
+    ) -> Result<(PathBuf, RepairReport), CommandError> {
+        let repaired_path = self.converted_path(source_hash, "repaired");
+        let known = lock(&self.repairs).get(source_hash).copied();
+        if let Some(report) = known.filter(|_| repaired_path.exists()) {
+            return Ok((repaired_path, report));
+        }
+
+        let report = repair(&repaired_path)?;
+        lock(&self.repairs).insert(source_hash.to_string(), report);
+        Ok((repaired_path, report))
+    }
+
+    /// Keeps `path` for as long as the document is open.
+    pub(crate) fn track(&self, document_id: &str, path: &Path) {
+        lock(&self.owners)
+            .entry(document_id.to_string())
+            .or_default()
+            .insert(path.to_path_buf());
+    }
+
+    /// Forgets the files of a closed document, returning those no other document uses. The
+    /// caller removes them once pdfium no longer holds them open.
+    pub(crate) fn release(&self, document_id: &str) -> Vec<PathBuf> {
+        let mut owners = lock(&self.owners);
+        let Some(paths) = owners.remove(document_id) else {
+            return Vec::new();
+        };
+        paths
+            .into_iter()
+            .filter(|path| !owners.values().any(|used| used.contains(path)))
+            .collect()
+    }
+
+    /// Removes every temporary file, when the app exits.
+    pub(crate) fn purge(&self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
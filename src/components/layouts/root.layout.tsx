@@ -1,11 +1,12 @@
 import { Outlet } from 'react-router-dom'
 import Header from '../partials/header.partial'
 import { useEffect } from 'react'
-import { useThemeStore } from '@/lib/stores/settings.store'
+import { useRenderSettingsStore, useThemeStore } from '@/lib/stores/settings.store'
 import { useTranslation } from 'react-i18next'
 
 export default function RootLayout() {const {i18n}= useTranslation()
 const {toggleDarkMode} = useThemeStore()
+const {renderSettings, setRenderSettings} = useRenderSettingsStore()
 useEffect(() => {
   i18n.changeLanguage(localStorage.getItem('language') ?? 'en')
 }, [i18n])
@@ -14,6 +15,11 @@ useEffect(() => {
   const theme = JSON.parse(localStorage.getItem('isDarkMode') ?? 'false')
   toggleDarkMode(theme)
 }, [toggleDarkMode])
+
+// The backend starts with its defaults, so hand it the saved render settings
+useEffect(() => {
+  setRenderSettings(renderSettings)
+}, [])
   return (
     <div className='flex flex-col w-full min-h-screen'>
       <Header />
@@ -1,7 +1,7 @@
 import { Dialog, DialogContent, DialogTitle } from '@/components/ui/dialog'
-import { FullImageData, ThumbnailData } from '@/lib/types/file-upload.types'
+import { FullImageData, RegionImageData, ThumbnailData } from '@/lib/types/file-upload.types'
 import { invoke } from '@tauri-apps/api/core'
-import { useEffect, useState } from 'react'
+import { useEffect, useRef, useState } from 'react'
 import { XIcon } from 'lucide-react'
 
 type Props = {
@@ -11,8 +11,18 @@ type Props = {
   password?: string
 }
 
+type Offset = { x: number, y: number }
+
+const MAX_ZOOM = 32
+const ZOOM_STEP = 1.2
+
 export default function FullScreenThumbnail({ isOpen, onClose, thumbnail, password }: Props) {
   const [imageData, setImageData] = useState<FullImageData | null>(null)
+  const [zoom, setZoom] = useState<number>(1)
+  // Position of the page's top left corner within the viewer, in CSS pixels
+  const [offset, setOffset] = useState<Offset>({ x: 0, y: 0 })
+  const [tile, setTile] = useState<RegionImageData | null>(null)
+  const dragStart = useRef<{ mouse: Offset, offset: Offset } | null>(null)
 
   const generateFullResolutionImage = async () => {
     const fullImageData = await invoke('gen_full_res', {
@@ -25,6 +35,9 @@ export default function FullScreenThumbnail({ isOpen, onClose, thumbnail, passwo
 
   useEffect(() => {
     if (isOpen) {
+      setZoom(1)
+      setOffset({ x: 0, y: 0 })
+      setTile(null)
       generateFullResolutionImage()
     }
   }, [isOpen])
@@ -48,6 +61,72 @@ export default function FullScreenThumbnail({ isOpen, onClose, thumbnail, passwo
     displayWidth = maxHeight * aspectRatio
   }
 
+  const viewerWidth = displayWidth * 1.1
+  const viewerHeight = displayHeight * 1.1
+  // Page points per CSS pixel at the current zoom
+  const pointsPerPixel = imageData ? imageData.page_width / (viewerWidth * zoom) : 1
+
+  const clampOffset = (next: Offset, nextZoom: number): Offset => ({
+    x: Math.min(0, Math.max(viewerWidth - viewerWidth * nextZoom, next.x)),
+    y: Math.min(0, Math.max(viewerHeight - viewerHeight * nextZoom, next.y)),
+  })
+
+  // Once zooming or panning settles, render the visible part of the page at screen resolution
+  useEffect(() => {
+    if (!imageData || zoom <= 1) {
+      setTile(null)
+      return
+    }
+
+    let cancelled = false
+    const timeout = setTimeout(async () => {
+      try {
+        const region = await invoke('render_region', {
+          filePath: thumbnail.file_path,
+          pageIndex: thumbnail.page_index,
+          password,
+          region: {
+            x: -offset.x * pointsPerPixel,
+            y: -offset.y * pointsPerPixel,
+            width: viewerWidth * pointsPerPixel,
+            height: viewerHeight * pointsPerPixel,
+          },
+          zoom: window.devicePixelRatio / pointsPerPixel,
+        }) as RegionImageData
+        if (!cancelled) setTile(region)
+      } catch (e) {
+        console.error(e)
+      }
+    }, 200)
+
+    return () => {
+      cancelled = true
+      clearTimeout(timeout)
+    }
+  }, [imageData, zoom, offset])
+
+  const handleWheel = (e: React.WheelEvent<HTMLDivElement>) => {
+    const bounds = e.currentTarget.getBoundingClientRect()
+    const cursor = { x: e.clientX - bounds.left, y: e.clientY - bounds.top }
+    const nextZoom = Math.min(MAX_ZOOM, Math.max(1, e.deltaY < 0 ? zoom * ZOOM_STEP : zoom / ZOOM_STEP))
+
+    // Keep the point under the cursor in place
+    setOffset(clampOffset({
+      x: cursor.x - (cursor.x - offset.x) * nextZoom / zoom,
+      y: cursor.y - (cursor.y - offset.y) * nextZoom / zoom,
+    }, nextZoom))
+    setZoom(nextZoom)
+  }
+
+  const handleMouseMove = (e: React.MouseEvent<HTMLDivElement>) => {
+    if (!dragStart.current) return
+    const { mouse, offset: start } = dragStart.current
+    setOffset(clampOffset({
+      x: start.x + e.clientX - mouse.x,
+      y: start.y + e.clientY - mouse.y,
+    }, zoom))
+  }
+
   return (
     <Dialog open={isOpen} onOpenChange={onClose}>
       <DialogTitle className='sr-only'>Fullscreen Thumbnail</DialogTitle>
@@ -56,17 +135,50 @@ export default function FullScreenThumbnail({ isOpen, onClose, thumbnail, passwo
           {!imageData && <span className="text-white">Loading...</span>}
           {imageData && (
             <>
-              <img
-                className="object-contain block"
-                src={imageData.data_url}
-                alt="Fullscreen Thumbnail"
+              <div
+                className={`relative overflow-hidden ${zoom > 1 ? 'cursor-grab' : ''}`}
                 style={{
                   maxWidth: `${maxWidth * 1.1}px`,
                   maxHeight: `${maxHeight * 1.1}px`,
-                  width: `${displayWidth * 1.1}px`,
-                  height: `${displayHeight * 1.1}px`,
+                  width: `${viewerWidth}px`,
+                  height: `${viewerHeight}px`,
                 }}
-              />
+                onWheel={handleWheel}
+                onMouseDown={e => {
+                  dragStart.current = { mouse: { x: e.clientX, y: e.clientY }, offset }
+                }}
+                onMouseMove={handleMouseMove}
+                onMouseUp={() => { dragStart.current = null }}
+                onMouseLeave={() => { dragStart.current = null }}
+              >
+                <img
+                  className="absolute object-contain block max-w-none select-none"
+                  src={imageData.url}
+                  alt="Fullscreen Thumbnail"
+                  draggable={false}
+                  style={{
+                    left: `${offset.x}px`,
+                    top: `${offset.y}px`,
+                    width: `${viewerWidth * zoom}px`,
+                    height: `${viewerHeight * zoom}px`,
+                  }}
+                />
+                {tile && (
+                  // Positioned by its page coordinates, so it stays in place while panning
+                  <img
+                    className="absolute block max-w-none select-none pointer-events-none"
+                    src={tile.url}
+                    alt=""
+                    draggable={false}
+                    style={{
+                      left: `${offset.x + tile.region.x / pointsPerPixel}px`,
+                      top: `${offset.y + tile.region.y / pointsPerPixel}px`,
+                      width: `${tile.region.width / pointsPerPixel}px`,
+                      height: `${tile.region.height / pointsPerPixel}px`,
+                    }}
+                  />
+                )}
+              </div>
               <button
                 onClick={onClose}
                 className="absolute top-2 right-2 z-10 w-8 h-8 flex items-center justify-center rounded-full bg-red-600 text-white hover:bg-red-700 transition-colors shadow"
@@ -15,6 +15,8 @@ import DownloadFileForm from './download-file-form'
 import { Separator } from '@/components/ui/separator'
 import { useTranslation } from 'react-i18next'
 import { toast } from 'sonner'
+import { invoke } from '@tauri-apps/api/core'
+import { errorMessage } from '@/lib/errors'
 
 type Props = {
   document: DocumentData
@@ -122,7 +124,7 @@ export default function Document({
       let res = await handleDownloadDocument(document, pagesInDocument, thumbnailsLookup, documents)
       toast.success(t('documents.download_all_form.messages.download_success', { filePath: res.file_path }))
     } catch (error) {
-      toast.error(t('documents.download_all_form.messages.download_error'))
+      toast.error(t('documents.download_all_form.messages.download_error'), { description: errorMessage(t, error) })
     } finally {
       setIsDownloading(false)
     }
@@ -208,7 +210,10 @@ export default function Document({
             </Button>
             )}
             <Button
-              onClick={() => removeDocument(document.id)}
+              onClick={() => {
+                invoke('close_document', { documentId: document.id }).catch(console.error)
+                removeDocument(document.id)
+              }}
               variant='destructive'
               className='w-fit cursor-pointer flex items-center gap-2'
             >
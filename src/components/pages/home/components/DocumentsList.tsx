@@ -13,6 +13,7 @@ import { documentRequiresDecryption } from '@/functions/document'
 import DownloadButton from './download-button'
 import DownloadAllForm from './download-all-form'
 import ResetButton from './ResetButton'
+import { OpenProjectButton, SaveProjectButton } from './ProjectButtons'
 
 export default function DocumentsList() {
   const { isFormOpen } = useDownloadAllStore()
@@ -134,6 +135,8 @@ export default function DocumentsList() {
           <div className='flex flex-col w-fit'>
             <div className='flex items-center gap-2'>
               <ResetButton />
+              <OpenProjectButton />
+              <SaveProjectButton />
               <DownloadButton className="
   bg-white text-slate-800 border-slate-300
   dark:bg-[#334155] dark:text-white dark:border-slate-500
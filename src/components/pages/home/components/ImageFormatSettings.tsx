@@ -0,0 +1,109 @@
+import { Button } from '@/components/ui/button'
+import { Input } from '@/components/ui/input'
+import { Label } from '@/components/ui/label'
+import { Popover, PopoverContent, PopoverTrigger } from '@/components/ui/popover'
+import { ToggleGroup, ToggleGroupItem } from '@/components/ui/toggle-group'
+import { useRenderSettingsStore } from '@/lib/stores/settings.store'
+import { ImageEncoding, OutputFormat, RenderSettings } from '@/lib/types/settings.types'
+import { cn } from '@/lib/utils'
+import { ImageIcon } from 'lucide-react'
+import { useTranslation } from 'react-i18next'
+
+const formats: { value: OutputFormat, label: string }[] = [
+  { value: 'Png', label: 'PNG' },
+  { value: 'WebpLossless', label: 'WebP' },
+  { value: 'WebpLossy', label: 'WebP (lossy)' },
+  { value: 'Jpeg', label: 'JPEG' },
+  { value: 'Avif', label: 'AVIF' },
+]
+
+const isLossy = (format: OutputFormat) => format === 'WebpLossy' || format === 'Jpeg' || format === 'Avif'
+
+export default function ImageFormatSettings() {
+  const { t } = useTranslation()
+  const { renderSettings, setRenderSettings } = useRenderSettingsStore()
+
+  const updateEncoding = (target: keyof RenderSettings, encoding: Partial<ImageEncoding>) => {
+    setRenderSettings({
+      ...renderSettings,
+      [target]: { ...renderSettings[target], ...encoding },
+    })
+  }
+
+  const renderEncoding = (target: keyof RenderSettings, label: string) => {
+    const encoding = renderSettings[target]
+
+    return (
+      <div className='flex flex-col gap-2'>
+        <Label className='text-sm font-medium text-slate-700 dark:text-slate-300'>{label}</Label>
+        <ToggleGroup
+          type='single'
+          value={encoding.format}
+          onValueChange={(val: string) => {
+            if (val) updateEncoding(target, { format: val as OutputFormat })
+          }}
+          className='w-full flex flex-wrap gap-1'
+        >
+          {formats.map(format => (
+            <ToggleGroupItem
+              key={format.value}
+              value={format.value}
+              className='
+                px-2 text-xs
+                bg-slate-100 dark:bg-slate-800
+                data-[state=on]:bg-slate-700 data-[state=on]:text-white dark:data-[state=on]:bg-slate-700 dark:data-[state=on]:text-white
+                transition-colors duration-200
+                rounded-md
+              '
+            >
+              {format.label}
+            </ToggleGroupItem>
+          ))}
+        </ToggleGroup>
+        {isLossy(encoding.format) && (
+          <div className='flex items-center gap-2'>
+            <Label htmlFor={`${target}-quality`} className='text-xs'>
+              {t('settings.image_format.quality', { defaultValue: 'Quality' })}
+            </Label>
+            <Input
+              id={`${target}-quality`}
+              type='number'
+              min={1}
+              max={100}
+              value={encoding.quality}
+              onChange={e => {
+                const quality = Math.min(100, Math.max(1, Number(e.target.value) || 1))
+                updateEncoding(target, { quality })
+              }}
+              className='h-8 w-20'
+            />
+          </div>
+        )}
+      </div>
+    )
+  }
+
+  return (
+    <Popover>
+      <PopoverTrigger asChild>
+        <Button
+          variant='outline'
+          aria-label={t('settings.image_format.title', { defaultValue: 'Image format' })}
+          className={cn(
+            'flex items-center gap-2 px-3 py-2 rounded-full shadow-sm border w-fit h-10 transition-colors',
+            'bg-white text-slate-800 border-slate-300',
+            'dark:bg-[#334155] dark:text-white dark:border-slate-500',
+            'hover:bg-slate-100 hover:text-slate-900',
+            'dark:hover:bg-[#232e41] dark:hover:text-white'
+          )}
+        >
+          <ImageIcon className='w-5 h-5' style={{ color: 'currentColor' }} />
+        </Button>
+      </PopoverTrigger>
+      <PopoverContent className='w-80 flex flex-col gap-4 rounded-lg shadow-lg border'>
+        {renderEncoding('thumbnails', t('settings.image_format.thumbnails', { defaultValue: 'Thumbnails' }))}
+        {renderEncoding('full_res', t('settings.image_format.full_res', { defaultValue: 'Full resolution' }))}
+      </PopoverContent>
+    </Popover>
+  )
+}
@@ -0,0 +1,48 @@
+import { Label } from '@/components/ui/label'
+import { ToggleGroup, ToggleGroupItem } from '@/components/ui/toggle-group'
+import { OverwritePolicy } from '@/lib/types/file-upload.types'
+import { useTranslation } from 'react-i18next'
+
+const policies: OverwritePolicy[] = ['Unique', 'Overwrite', 'Fail']
+
+type Props = {
+  value: OverwritePolicy
+  onChange: (policy: OverwritePolicy) => void
+}
+
+/// What an export does when a file with its name already exists in the Downloads folder
+export default function OverwritePolicySelect({ value, onChange }: Props) {
+  const { t } = useTranslation()
+
+  return (
+    <div className='flex flex-col gap-2 w-full mt-2'>
+      <Label className='text-sm font-medium text-slate-700 dark:text-slate-300'>
+        {t('document.download_form.overwrite.label')}
+      </Label>
+      <ToggleGroup
+        type='single'
+        value={value}
+        onValueChange={(val: string) => {
+          if (val) onChange(val as OverwritePolicy)
+        }}
+        className='w-full flex gap-2'
+      >
+        {policies.map(policy => (
+          <ToggleGroupItem
+            key={policy}
+            value={policy}
+            className={`
+              px-2 text-xs
+              bg-slate-100 dark:bg-slate-800
+              data-[state=on]:bg-slate-700 data-[state=on]:text-white dark:data-[state=on]:bg-slate-700 dark:data-[state=on]:text-white
+              transition-colors duration-200
+              rounded-md
+            `}
+          >
+            {t(`document.download_form.overwrite.${policy}`)}
+          </ToggleGroupItem>
+        ))}
+      </ToggleGroup>
+    </div>
+  )
+}
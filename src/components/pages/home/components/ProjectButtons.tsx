@@ -0,0 +1,63 @@
+import { Button } from '@/components/ui/button'
+import { openProject, saveProject } from '@/functions/project'
+import { useDownloadAllStore, usePdfPreviewsStore } from '@/lib/stores/file-upload.store'
+import { FolderOpen, Save } from 'lucide-react'
+import { useTranslation } from 'react-i18next'
+import { toast } from 'sonner'
+import { errorMessage } from '@/lib/errors'
+
+export function SaveProjectButton() {
+  const {t} = useTranslation()
+  const { documents } = usePdfPreviewsStore()
+  const { exportOptions } = useDownloadAllStore()
+
+  const handleSave = async () => {
+    try {
+      const res = await saveProject(documents, exportOptions)
+      if (res) toast.success(t('project.messages.saved', { filePath: res.file_path }))
+    } catch (error) {
+      console.error('Error saving project:', error)
+      toast.error(t('project.messages.save_error'), { description: errorMessage(t, error) })
+    }
+  }
+
+  return (
+    <Button variant='outline' onClick={handleSave}>
+      <Save />
+      {t('project.save_btn')}
+    </Button>
+  )
+}
+
+export function OpenProjectButton() {
+  const {t} = useTranslation()
+  const { setDocuments } = usePdfPreviewsStore()
+  const { setExportOptions } = useDownloadAllStore()
+
+  const handleOpen = async () => {
+    try {
+      const res = await openProject()
+      if (!res) return
+
+      // Opened anyway, the user decides whether the project is still usable
+      res.sources.forEach(source => {
+        if (source.state === 'Missing') toast.error(t('project.messages.missing', { filePath: source.file_path }))
+        if (source.state === 'Modified') toast.warning(t('project.messages.modified', { filePath: source.file_path }))
+      })
+
+      setDocuments(res.documents)
+      setExportOptions(res.exportOptions)
+      toast.success(t('project.messages.opened'))
+    } catch (error) {
+      console.error('Error opening project:', error)
+      toast.error(t('project.messages.open_error'), { description: errorMessage(t, error) })
+    }
+  }
+
+  return (
+    <Button variant='outline' onClick={handleOpen}>
+      <FolderOpen />
+      {t('project.open_btn')}
+    </Button>
+  )
+}
@@ -0,0 +1,216 @@
+import { Button } from '@/components/ui/button'
+import { Dialog, DialogContent, DialogDescription, DialogHeader, DialogTitle, DialogTrigger } from '@/components/ui/dialog'
+import { Input } from '@/components/ui/input'
+import { Label } from '@/components/ui/label'
+import { Switch } from '@/components/ui/switch'
+import { ToggleGroup, ToggleGroupItem } from '@/components/ui/toggle-group'
+import { deleteRecipe, listRecipes, runRecipe, saveRecipe, unwatchRecipe, watchRecipe } from '@/functions/recipe'
+import { errorMessage } from '@/lib/errors'
+import { useDownloadAllStore } from '@/lib/stores/file-upload.store'
+import { EncryptionLevel, MergeRecipe, RecipeRunEvent } from '@/lib/types/file-upload.types'
+import { cn } from '@/lib/utils'
+import { listen } from '@tauri-apps/api/event'
+import { open } from '@tauri-apps/plugin-dialog'
+import { FolderOpen, Play, Trash2, Workflow } from 'lucide-react'
+import { useEffect, useState } from 'react'
+import { useTranslation } from 'react-i18next'
+import { toast } from 'sonner'
+
+const emptyRecipe = (): MergeRecipe => ({
+  id: '',
+  name: '',
+  file_name: '',
+  folder: '',
+  inputs: [],
+  export_options: {}
+})
+
+export default function Recipes() {
+  const { t } = useTranslation()
+  const { exportOptions } = useDownloadAllStore()
+  const [recipes, setRecipes] = useState<MergeRecipe[]>([])
+  const [watched, setWatched] = useState<string[]>([])
+  const [draft, setDraft] = useState<MergeRecipe>(emptyRecipe())
+  const [patterns, setPatterns] = useState('')
+  const [password, setPassword] = useState('')
+
+  const refresh = async () => {
+    const res = await listRecipes()
+    setRecipes(res.recipes)
+    setWatched(res.watched)
+  }
+
+  const showError = (error: unknown) => {
+    toast.error(t('recipes.messages.run_error', { error: errorMessage(t, error) }))
+  }
+
+  useEffect(() => {
+    refresh().catch(console.error)
+
+    // Runs of watched recipes happen in the background and only report back through this event
+    const unlisten = listen<RecipeRunEvent>('recipe-run', event => {
+      const { result, error } = event.payload
+      if (result) toast.success(t('documents.download_all_form.messages.download_success', { filePath: result.file_path }))
+      if (error) showError(error)
+    })
+    return () => { unlisten.then(fn => fn()) }
+  }, [])
+
+  const handlePickFolder = async () => {
+    const folder = await open({ directory: true, multiple: false })
+    if (folder) setDraft({ ...draft, folder })
+  }
+
+  const handleSave = async () => {
+    try {
+      const inputs = patterns.split('\n').map(line => line.trim()).filter(Boolean).map(pattern => ({ pattern }))
+      // Saved with the export settings currently chosen for merging
+      await saveRecipe({ ...draft, inputs, export_options: exportOptions })
+      setDraft(emptyRecipe())
+      setPatterns('')
+      await refresh()
+    } catch (error) {
+      console.error('Error saving recipe:', error)
+      toast.error(t('recipes.messages.save_error'))
+    }
+  }
+
+  const handleRun = async (recipe: MergeRecipe) => {
+    try {
+      const res = await runRecipe(recipe.id, password)
+      toast.success(t('documents.download_all_form.messages.download_success', { filePath: res.file_path }))
+    } catch (error) {
+      console.error('Error running recipe:', error)
+      showError(error)
+    }
+  }
+
+  const handleWatch = async (recipe: MergeRecipe, enabled: boolean) => {
+    try {
+      if (enabled) await watchRecipe(recipe.id, password)
+      else await unwatchRecipe(recipe.id)
+      await refresh()
+    } catch (error) {
+      console.error('Error watching recipe:', error)
+      showError(error)
+    }
+  }
+
+  const handleDelete = async (recipe: MergeRecipe) => {
+    try {
+      await deleteRecipe(recipe.id)
+      await refresh()
+    } catch (error) {
+      console.error('Error deleting recipe:', error)
+      toast.error(t('recipes.messages.save_error'))
+    }
+  }
+
+  const canSave = draft.name.length > 0 && draft.file_name.length > 0 && draft.folder.length > 0 && patterns.trim().length > 0
+
+  return (
+    <Dialog>
+      <DialogTrigger asChild>
+        <Button
+          variant='outline'
+          aria-label={t('recipes.title')}
+          className={cn(
+            'flex items-center gap-2 px-3 py-2 rounded-full shadow-sm border w-fit h-10 transition-colors',
+            'bg-white text-slate-800 border-slate-300',
+            'dark:bg-[#334155] dark:text-white dark:border-slate-500',
+            'hover:bg-slate-100 hover:text-slate-900',
+            'dark:hover:bg-[#232e41] dark:hover:text-white'
+          )}
+        >
+          <Workflow className='w-5 h-5' style={{ color: 'currentColor' }} />
+        </Button>
+      </DialogTrigger>
+      <DialogContent className='max-h-[85vh] overflow-y-auto'>
+        <DialogHeader>
+          <DialogTitle>{t('recipes.title')}</DialogTitle>
+          <DialogDescription>{t('recipes.description')}</DialogDescription>
+        </DialogHeader>
+
+        <div className='flex flex-col gap-2'>
+          {recipes.length <= 0 && (
+            <p className='text-sm text-slate-500'>{t('recipes.empty')}</p>
+          )}
+          {recipes.map(recipe => (
+            <div key={recipe.id} className='flex items-center gap-2 rounded-md border p-2'>
+              <div className='flex flex-col grow min-w-0'>
+                <span className='text-sm font-medium truncate'>{recipe.name}</span>
+                <span className='text-xs text-slate-500 truncate'>
+                  {recipe.inputs.map(input => input.pattern).join(', ')}
+                </span>
+              </div>
+              <div className='flex items-center gap-1'>
+                <Switch
+                  id={`watch-${recipe.id}`}
+                  checked={watched.includes(recipe.id)}
+                  onCheckedChange={checked => handleWatch(recipe, checked)}
+                />
+                <Label htmlFor={`watch-${recipe.id}`} className='text-xs'>{t('recipes.watch')}</Label>
+              </div>
+              <Button size='icon' variant='outline' aria-label={t('recipes.run')} onClick={() => handleRun(recipe)}>
+                <Play />
+              </Button>
+              <Button size='icon' variant='destructive' aria-label={t('recipes.delete')} onClick={() => handleDelete(recipe)}>
+                <Trash2 />
+              </Button>
+            </div>
+          ))}
+          {recipes.some(recipe => recipe.encryption_level) && (
+            <Input
+              type='password'
+              placeholder={t('recipes.password')}
+              value={password}
+              onChange={e => setPassword(e.target.value)}
+            />
+          )}
+        </div>
+
+        <div className='flex flex-col gap-2 border-t pt-4'>
+          <Label className='text-sm font-medium'>{t('recipes.new')}</Label>
+          <Input
+            placeholder={t('recipes.name')}
+            value={draft.name}
+            onChange={e => setDraft({ ...draft, name: e.target.value })}
+          />
+          <Input
+            placeholder={t('recipes.file_name')}
+            value={draft.file_name}
+            onChange={e => setDraft({ ...draft, file_name: e.target.value })}
+          />
+          <div className='flex items-center gap-2'>
+            <Input readOnly placeholder={t('recipes.folder')} value={draft.folder} className='grow' />
+            <Button size='icon' variant='outline' aria-label={t('recipes.folder')} onClick={handlePickFolder}>
+              <FolderOpen />
+            </Button>
+          </div>
+          <textarea
+            placeholder={t('recipes.patterns')}
+            value={patterns}
+            onChange={e => setPatterns(e.target.value)}
+            rows={4}
+            className='rounded-md border bg-transparent px-3 py-2 text-sm'
+          />
+          <ToggleGroup
+            type='single'
+            value={draft.encryption_level ?? 'None'}
+            onValueChange={(val: string) => {
+              if (val) setDraft({ ...draft, encryption_level: val === 'None' ? undefined : val as EncryptionLevel })
+            }}
+            className='w-full flex gap-1'
+          >
+            <ToggleGroupItem value='None' className='px-2 text-xs'>{t('recipes.no_encryption')}</ToggleGroupItem>
+            <ToggleGroupItem value='Aes128' className='px-2 text-xs'>AES-128</ToggleGroupItem>
+            <ToggleGroupItem value='Aes256' className='px-2 text-xs'>AES-256</ToggleGroupItem>
+          </ToggleGroup>
+          <Button disabled={!canSave} onClick={handleSave}>
+            {t('recipes.save')}
+          </Button>
+        </div>
+      </DialogContent>
+    </Dialog>
+  )
+}
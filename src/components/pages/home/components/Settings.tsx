@@ -1,9 +1,13 @@
+import ImageFormatSettings from './ImageFormatSettings'
 import LanguageSwitcher from './LanguageSwitcher'
+import Recipes from './Recipes'
 import ThemeToggle from './ThemeToggle'
 
 export default function Settings() {
   return (
     <div className='flex items-center gap-4 absolute bottom-4 right-2 z-50'>
+      <Recipes />
+      <ImageFormatSettings />
       <ThemeToggle/>
       <LanguageSwitcher />
     </div>
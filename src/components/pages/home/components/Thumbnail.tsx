@@ -90,7 +90,7 @@ export default function Thumbnail({ thumbnail, index, group, password }: Props)
           </Button>
         </div>
         <div className='w-fit overflow-hidden rounded-md'>
-          <img src={thumbnail.thumbnail} alt={`Thumbnail ${index}`} className="h-[140px] w-auto object-contain" />
+          <img src={thumbnail.thumbnail} loading="lazy" alt={`Thumbnail ${index}`} className="h-[140px] w-auto object-contain" />
         </div>
       </div>
       <FullScreenThumbnail isOpen={showFullScreen} onClose={() => setShowFullScreen(false)} thumbnail={thumbnail} password={password} />
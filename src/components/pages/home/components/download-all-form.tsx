@@ -2,13 +2,30 @@ import { useState, useRef, useLayoutEffect, useEffect } from 'react'
 import { Input } from '@/components/ui/input'
 import { Button } from '@/components/ui/button'
 import { useDownloadAllStore } from '@/lib/stores/file-upload.store'
-import { Eye, EyeOff, BanIcon, DownloadIcon } from 'lucide-react'
-import { DocumentData, EncryptionLevel, ThumbnailData } from '@/lib/types/file-upload.types'
-import { handleDownloadAllDocuments } from '@/functions/document'
+import { Eye, EyeOff, BanIcon, DownloadIcon, SaveIcon } from 'lucide-react'
+import { DocumentData, EncryptionLevel, ExportOptions, OutputDestination, OverwritePolicy, PageBox, PageLayout, PaperSize, ThumbnailData } from '@/lib/types/file-upload.types'
+import { chooseSavePath, handleDownloadAllDocuments } from '@/functions/document'
 import { useTranslation } from 'react-i18next'
 import { toast } from 'sonner'
 import { ToggleGroup, ToggleGroupItem } from '@/components/ui/toggle-group'
 import { Label } from '@/components/ui/label'
+import { Switch } from '@/components/ui/switch'
+import { errorMessage } from '@/lib/errors'
+import OverwritePolicySelect from './OverwritePolicySelect'
+
+type LayoutChoice = 'single' | 'two_up' | 'four_up' | 'booklet'
+
+const layouts: Record<LayoutChoice, PageLayout | undefined> = {
+  single: undefined,
+  two_up: { type: 'NUp', columns: 2, rows: 1, gap: 12 },
+  four_up: { type: 'NUp', columns: 2, rows: 2, gap: 12 },
+  booklet: { type: 'Booklet' },
+}
+
+const isSameLayout = (a?: PageLayout, b?: PageLayout) => {
+  if (a?.type === 'NUp' && b?.type === 'NUp') return a.columns === b.columns && a.rows === b.rows
+  return a?.type === b?.type
+}
 
 type Props = {
   items: Record<string, string[]>
@@ -22,12 +39,33 @@ export default function DownloadAllForm({ items, thumbnailsLookup, documents }:
   const [confirmPassword, setConfirmPassword] = useState('')
   const [showPassword, setShowPassword] = useState(false)
   const [showConfirmPassword, setShowConfirmPassword] = useState(false)
-  const { setIsFormOpen, isFormOpen, setIsDownloading, isDownloading } = useDownloadAllStore()
+  const { setIsFormOpen, isFormOpen, setIsDownloading, isDownloading, exportOptions, setExportOptions } = useDownloadAllStore()
   const inputRef = useRef<HTMLInputElement>(null)
   const formRef = useRef<HTMLDivElement>(null)
   const [encryptionLevel, setEncryptionLevel] = useState<EncryptionLevel>('Aes128');
+  const [withCover, setWithCover] = useState(false)
+  const [overwrite, setOverwrite] = useState<OverwritePolicy>('Unique')
   const { t } = useTranslation()
 
+  // Export options live in the store, so they are saved with the project
+  const updateExportOptions = (options: Partial<ExportOptions>) => setExportOptions({ ...exportOptions, ...options })
+  // Crop keeps pages as they are shown
+  const trimTo = exportOptions.trim_to ?? 'Crop'
+  const setTrimTo = (pageBox: PageBox) => updateExportOptions({ trim_to: pageBox === 'Crop' ? undefined : pageBox })
+  const pageSize = exportOptions.page_size ?? 'Original'
+  const setPageSize = (size: PaperSize | 'Original') => updateExportOptions({ page_size: size === 'Original' ? undefined : size })
+  const layout = (Object.keys(layouts) as LayoutChoice[]).find(choice => isSameLayout(layouts[choice], exportOptions.layout)) ?? 'single'
+  const setLayout = (choice: LayoutChoice) => updateExportOptions({ layout: layouts[choice] })
+  const padToEven = exportOptions.pad_to_even ?? false
+  const setPadToEven = (pad_to_even: boolean) => updateExportOptions({ pad_to_even })
+  const withToc = !!exportOptions.table_of_contents
+  const withOutlines = exportOptions.table_of_contents?.outlines ?? false
+  const setTableOfContents = (enabled: boolean, outlines: boolean) => updateExportOptions({
+    table_of_contents: enabled
+      ? { title: t('documents.download_all_form.table_of_contents_title'), outlines }
+      : undefined
+  })
+
   useLayoutEffect(() => {
     if (isFormOpen && inputRef.current) {
       inputRef.current.focus()
@@ -62,23 +100,30 @@ export default function DownloadAllForm({ items, thumbnailsLookup, documents }:
     setConfirmPassword('')
     setIsFormOpen(false)
     setEncryptionLevel('Aes128')
+    setWithCover(false)
+    setOverwrite('Unique')
   }
 
-  const handleDownload = async () => {
+  const handleDownload = async (destination: OutputDestination) => {
     if (isDownloadEnabled) {
      try {
       setIsDownloading(true)
-      let res = await handleDownloadAllDocuments(fileName, items, thumbnailsLookup, documents, password, encryptionLevel)
+      let res = await handleDownloadAllDocuments(fileName, items, thumbnailsLookup, documents, password, encryptionLevel, exportOptions, withCover, destination)
       toast.success(t('documents.download_all_form.messages.download_success', { filePath: res.file_path }))
       handleReset()
      } catch (error) {
-      toast.error(t('documents.download_all_form.messages.download_error'))
+      toast.error(t('documents.download_all_form.messages.download_error'), { description: errorMessage(t, error) })
      } finally {
       setIsDownloading(false)
      }
     }
   }
 
+  const handleSaveAs = async () => {
+    const destination = await chooseSavePath(fileName)
+    if (destination) await handleDownload(destination)
+  }
+
   return (
     <div
       ref={formRef}
@@ -189,6 +234,119 @@ export default function DownloadAllForm({ items, thumbnailsLookup, documents }:
           </span>
         )}
       </div>
+      <div className='flex flex-col gap-2 w-full mt-2'>
+        <Label className='text-sm font-medium text-slate-700 dark:text-slate-300'>
+          {t('documents.download_all_form.page_area', { defaultValue: 'Page area' })}
+        </Label>
+        <ToggleGroup
+          type='single'
+          value={trimTo}
+          onValueChange={(val: string) => {
+            if (val) setTrimTo(val as PageBox)
+          }}
+          className='w-full flex gap-2'
+        >
+          {(['Crop', 'Trim', 'Bleed', 'Media'] as PageBox[]).map(pageBox => (
+            <ToggleGroupItem
+              key={pageBox}
+              value={pageBox}
+              className={`
+                bg-slate-100 dark:bg-slate-800
+                data-[state=on]:bg-slate-700 data-[state=on]:text-white dark:data-[state=on]:bg-slate-700 dark:data-[state=on]:text-white
+                transition-colors duration-200
+                rounded-md
+              `}
+            >
+              {t(`documents.download_all_form.page_box.${pageBox.toLowerCase()}`, { defaultValue: `${pageBox}Box` })}
+            </ToggleGroupItem>
+          ))}
+        </ToggleGroup>
+      </div>
+      <div className='flex flex-col gap-2 w-full mt-2'>
+        <Label className='text-sm font-medium text-slate-700 dark:text-slate-300'>
+          {t('documents.download_all_form.paper_size', { defaultValue: 'Paper size' })}
+        </Label>
+        <ToggleGroup
+          type='single'
+          value={pageSize}
+          onValueChange={(val: string) => {
+            if (val) setPageSize(val as PaperSize | 'Original')
+          }}
+          className='w-full flex flex-wrap gap-1'
+        >
+          {(['Original', 'A4', 'Letter', 'A3', 'A5', 'Legal', 'Tabloid'] as const).map(size => (
+            <ToggleGroupItem
+              key={size}
+              value={size}
+              className={`
+                px-2 text-xs
+                bg-slate-100 dark:bg-slate-800
+                data-[state=on]:bg-slate-700 data-[state=on]:text-white dark:data-[state=on]:bg-slate-700 dark:data-[state=on]:text-white
+                transition-colors duration-200
+                rounded-md
+              `}
+            >
+              {size === 'Original'
+                ? t('documents.download_all_form.original_size', { defaultValue: 'Original' })
+                : size}
+            </ToggleGroupItem>
+          ))}
+        </ToggleGroup>
+      </div>
+      <div className='flex flex-col gap-2 w-full mt-2'>
+        <Label className='text-sm font-medium text-slate-700 dark:text-slate-300'>
+          {t('documents.download_all_form.layout', { defaultValue: 'Layout' })}
+        </Label>
+        <ToggleGroup
+          type='single'
+          value={layout}
+          onValueChange={(val: string) => {
+            if (val) setLayout(val as LayoutChoice)
+          }}
+          className='w-full flex flex-wrap gap-1'
+        >
+          {(Object.keys(layouts) as LayoutChoice[]).map(choice => (
+            <ToggleGroupItem
+              key={choice}
+              value={choice}
+              className={`
+                px-2 text-xs
+                bg-slate-100 dark:bg-slate-800
+                data-[state=on]:bg-slate-700 data-[state=on]:text-white dark:data-[state=on]:bg-slate-700 dark:data-[state=on]:text-white
+                transition-colors duration-200
+                rounded-md
+              `}
+            >
+              {t(`documents.download_all_form.layouts.${choice}`)}
+            </ToggleGroupItem>
+          ))}
+        </ToggleGroup>
+      </div>
+      <div className='flex items-center justify-between gap-2 w-full mt-2'>
+        <Label htmlFor='pad-to-even' className='text-sm text-slate-700 dark:text-slate-300'>
+          {t('documents.download_all_form.pad_to_even')}
+        </Label>
+        <Switch id='pad-to-even' checked={padToEven} onCheckedChange={setPadToEven} />
+      </div>
+      <div className='flex items-center justify-between gap-2 w-full'>
+        <Label htmlFor='cover-page' className='text-sm text-slate-700 dark:text-slate-300'>
+          {t('documents.download_all_form.cover_page')}
+        </Label>
+        <Switch id='cover-page' checked={withCover} onCheckedChange={setWithCover} />
+      </div>
+      <div className='flex items-center justify-between gap-2 w-full'>
+        <Label htmlFor='table-of-contents' className='text-sm text-slate-700 dark:text-slate-300'>
+          {t('documents.download_all_form.table_of_contents')}
+        </Label>
+        <Switch id='table-of-contents' checked={withToc} onCheckedChange={checked => setTableOfContents(checked, withOutlines)} />
+      </div>
+      <div className='flex items-center justify-between gap-2 w-full'>
+        <Label htmlFor='outlines' className='text-sm text-slate-700 dark:text-slate-300'>
+          {t('documents.download_all_form.outlines')}
+        </Label>
+        <Switch id='outlines' checked={withOutlines} disabled={!withToc} onCheckedChange={checked => setTableOfContents(true, checked)} />
+      </div>
+      <OverwritePolicySelect value={overwrite} onChange={setOverwrite} />
       <div className='flex gap-2 justify-end mt-4'>
         <Button
           className='transition-all duration-300 cursor-pointer'
@@ -199,11 +357,20 @@ export default function DownloadAllForm({ items, thumbnailsLookup, documents }:
           <BanIcon/>
           <span>{t('documents.download_all_form.cancel')}</span>
         </Button>
+        <Button
+          variant='outline'
+          className='transition-all duration-300 cursor-pointer'
+          disabled={!isDownloadEnabled || isDownloading}
+          onClick={handleSaveAs}
+        >
+          <SaveIcon/>
+          <span>{t('document.download_form.save_as')}</span>
+        </Button>
         <Button
           variant='default'
           className='bg-slate-700 hover:bg-slate-800 transition-all duration-300 cursor-pointer'
           disabled={!isDownloadEnabled || isDownloading}
-          onClick={handleDownload}
+          onClick={() => handleDownload({ overwrite })}
         >
           <DownloadIcon/>
           <span>{isDownloading ? t('documents.downloading_text') : t('documents.download_all_form.download')}</span>
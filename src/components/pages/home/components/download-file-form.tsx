@@ -1,13 +1,15 @@
 import { useState, useEffect, useRef, useLayoutEffect } from 'react'
 import { Input } from '@/components/ui/input'
 import { Button } from '@/components/ui/button'
-import { BanIcon, DownloadIcon, Eye, EyeOff } from 'lucide-react'
-import { DocumentData, EncryptionLevel, ThumbnailData } from '@/lib/types/file-upload.types'
-import { handleDownloadDocument } from '@/functions/document'
+import { BanIcon, DownloadIcon, Eye, EyeOff, SaveIcon } from 'lucide-react'
+import { DocumentData, EncryptionLevel, OutputDestination, OverwritePolicy, ThumbnailData } from '@/lib/types/file-upload.types'
+import { chooseSavePath, handleDownloadDocument } from '@/functions/document'
 import { useTranslation } from 'react-i18next'
 import { toast } from 'sonner'
 import { ToggleGroup, ToggleGroupItem } from '@/components/ui/toggle-group'
 import { Label } from '@/components/ui/label'
+import { errorMessage } from '@/lib/errors'
+import OverwritePolicySelect from './OverwritePolicySelect'
 
 type Props = {
   doc: DocumentData
@@ -24,6 +26,7 @@ export default function DownloadFileForm({ doc, pagesInDocument, thumbnailsLooku
   const [showPassword, setShowPassword] = useState(false)
   const [showConfirmPassword, setShowConfirmPassword] = useState(false)
   const [encryptionLevel, setEncryptionLevel] = useState<EncryptionLevel>('Aes128');
+  const [overwrite, setOverwrite] = useState<OverwritePolicy>('Unique')
   const { t } = useTranslation()
   
   const inputRef = useRef<HTMLInputElement>(null)
@@ -60,21 +63,27 @@ export default function DownloadFileForm({ doc, pagesInDocument, thumbnailsLooku
     setConfirmPassword('')
     setIsDownloadFormOpen(false)
     setEncryptionLevel('Aes128')
+    setOverwrite('Unique')
   }
 
-  const handleDownload = async () => {
+  const handleDownload = async (destination: OutputDestination) => {
     if (isDownloadEnabled) {
       try {
-        let res = await handleDownloadDocument(doc, pagesInDocument, thumbnailsLookup, documents, password, encryptionLevel)
+        let res = await handleDownloadDocument(doc, pagesInDocument, thumbnailsLookup, documents, password, encryptionLevel, undefined, destination)
         toast.success(t('documents.download_all_form.messages.download_success', { filePath: res.file_path }))
         handleReset()
       } catch (error) {
         console.error(error)
-        toast.error(t('documents.download_all_form.messages.download_error'))
+        toast.error(t('documents.download_all_form.messages.download_error'), { description: errorMessage(t, error) })
       }
     }
   }
 
+  const handleSaveAs = async () => {
+    const destination = await chooseSavePath(doc.file_name)
+    if (destination) await handleDownload(destination)
+  }
+
   return (
     <div
       ref={formRef}
@@ -173,6 +182,7 @@ export default function DownloadFileForm({ doc, pagesInDocument, thumbnailsLooku
           </span>
         )}
       </div>
+      <OverwritePolicySelect value={overwrite} onChange={setOverwrite} />
       <div className='flex gap-2 justify-end mt-4'>
         <Button
           className='transition-all duration-300 cursor-pointer'
@@ -183,11 +193,20 @@ export default function DownloadFileForm({ doc, pagesInDocument, thumbnailsLooku
           <BanIcon/>
           <span>{t('document.download_form.cancel')}</span>
         </Button>
+        <Button
+          variant='outline'
+          className='transition-all duration-300 cursor-pointer'
+          disabled={!isDownloadEnabled}
+          onClick={handleSaveAs}
+        >
+          <SaveIcon/>
+          <span>{t('document.download_form.save_as')}</span>
+        </Button>
         <Button
           variant='default'
           className='bg-slate-700 hover:bg-slate-800 transition-all duration-300 cursor-pointer'
           disabled={!isDownloadEnabled}
-          onClick={handleDownload}
+          onClick={() => handleDownload({ overwrite })}
         >
           <DownloadIcon/>
           <span>{t('document.download_form.download')}</span>
@@ -1,6 +1,10 @@
 import { toast } from 'sonner'
-import { DocumentData } from '@/lib/types/file-upload.types'
+import { CommandError, DocumentData, ThumbnailData, ThumbnailReadyEvent, ThumbnailsFinishedEvent } from '@/lib/types/file-upload.types'
+import { usePdfPreviewsStore } from '@/lib/stores/file-upload.store'
+import { isPasswordError } from '@/lib/errors'
+import i18n from '@/i18n/config'
 import { invoke } from '@tauri-apps/api/core'
+import { listen } from '@tauri-apps/api/event'
 import { open } from '@tauri-apps/plugin-dialog'
 
 export async function handleFileUpload(isProcessing: boolean, incrementProcessedFiles: () => void, decrementProcessedFiles: () => void, addFilePath: (filePath: string) => void, addPdfPreview: (pdfPreview: DocumentData) => void): Promise<void> {
@@ -42,33 +46,65 @@ export async function handleFileUpload(isProcessing: boolean, incrementProcessed
 }
 
 export async function generateThumbnails(filePath: string, addPdfPreview: (pdfPreview: DocumentData) => void, password?: string, documentId?: string) {
-  try {
-    const pdfPreviewData = await invoke('generate_thumbnails', { filePath, password, documentId }) as DocumentData
-    if (!pdfPreviewData.error) {
-      addPdfPreview({
-        ...pdfPreviewData,
-        decrypted: true,
-        password
-      })
-      if (password) {
-        toast.success("Password correct. Document decrypted.")
-      }
-    } else if (pdfPreviewData.error === 'PDF_PASSWORD_INCORRECT' || pdfPreviewData.error === 'PDF_PASSWORD_REQUIRED') {
-      console.log("pdfPreviewData.error in else if", pdfPreviewData.error)
-      addPdfPreview({
-        ...pdfPreviewData,
-        decrypted: false,
-        password: ''
-      })
+  const id = documentId ?? crypto.randomUUID()
+  // Thumbnails may arrive before the document is added to the store, so hold them back until then
+  let isAdded = false
+  const pending: ThumbnailData[] = []
 
-      if (pdfPreviewData.error == "PDF_PASSWORD_INCORRECT") {
-        toast.error("Password incorrect. Please try again.")
-      }
+  const unlistenReady = await listen<ThumbnailReadyEvent>('thumbnail-ready', event => {
+    if (event.payload.document_id !== id) return
+    if (isAdded) {
+      usePdfPreviewsStore.getState().addThumbnail(id, event.payload.thumbnail)
+    } else {
+      pending.push(event.payload.thumbnail)
+    }
+  })
+  const unlistenFinished = await listen<ThumbnailsFinishedEvent>('thumbnails-finished', event => {
+    if (event.payload.document_id !== id) return
+    if (event.payload.error) console.error(event.payload.error)
+    unlistenReady()
+    unlistenFinished()
+  })
+
+  try {
+    const pdfPreviewData = await invoke('generate_thumbnails', { filePath, password, documentId: id }) as DocumentData
+    addPdfPreview({
+      ...pdfPreviewData,
+      thumbnails: pending.sort((a, b) => a.page_index - b.page_index),
+      decrypted: true,
+      password
+    })
+    isAdded = true
+    if (pdfPreviewData.repair) {
+      toast.warning(i18n.t('document.messages.repaired', { file: pdfPreviewData.file_name, lost: pdfPreviewData.repair.objects_lost }))
+    }
+    if (password) {
+      toast.success("Password correct. Document decrypted.")
     }
   } catch (e) {
+    // Nothing is rendered for documents that failed to open
+    unlistenReady()
+    unlistenFinished()
     console.error(e);
-    if (e === 'PDF_PASSWORD_INCORRECT') {
-      console.log("e in catch", e)
+    if (isPasswordError(e)) {
+      addPdfPreview(lockedDocument(id, filePath, e))
+      if (e.code === 'PDF_PASSWORD_INCORRECT') {
+        toast.error("Password incorrect. Please try again.")
+      }
     }
   }
-}
\ No newline at end of file
+}
+
+/// A document that needs its password before any page can be shown
+export function lockedDocument(id: string, filePath: string, error: CommandError): DocumentData {
+  return {
+    id,
+    file_name: error.file_name ?? filePath,
+    file_path: filePath,
+    source_path: filePath,
+    thumbnails: [],
+    page_count: 0,
+    decrypted: false,
+    password: ''
+  }
+}
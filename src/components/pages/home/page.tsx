@@ -3,6 +3,7 @@ import { usePdfPreviewsStore } from '@/lib/stores/file-upload.store'
 import DocumentsList from './components/DocumentsList'
 import { useTranslation } from 'react-i18next'
 import Settings from './components/Settings'
+import { OpenProjectButton } from './components/ProjectButtons'
 
 export default function HomePage() {
   const {t} = useTranslation()
@@ -14,7 +15,8 @@ export default function HomePage() {
           <div className='flex flex-col gap-1 pt-48 items-center'>
             <h1 className='text-4xl font-bold'>{t('landing.title')}</h1>
             <h2 className='text-xl font-medium'>{t('landing.subtitle')}</h2>
-            <div className='w-full mt-8 flex justify-center'>
+            <div className='w-full mt-8 flex justify-center gap-2'>
+              <OpenProjectButton />
               <UploadButton />
             </div>
             
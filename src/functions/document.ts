@@ -1,10 +1,43 @@
-import { DocumentData, DownloadResponse, EncryptionLevel, ThumbnailData } from '@/lib/types/file-upload.types'
+import i18n from '@/i18n/config'
+import { DocumentData, DownloadResponse, EncryptionLevel, ExportOptions, GeneratedPage, OutputDestination, PreflightReport, ThumbnailData } from '@/lib/types/file-upload.types'
 import { CollisionPriority } from '@dnd-kit/abstract'
 import { RestrictToVerticalAxis } from '@dnd-kit/abstract/modifiers'
 import { useSortable } from '@dnd-kit/react/sortable'
 import { invoke } from '@tauri-apps/api/core'
+import { save } from '@tauri-apps/plugin-dialog'
+import { toast } from 'sonner'
 
-export const handleDownloadDocument = async (document: DocumentData, pagesInDocument: string[], thumbnailsLookup: Record<string, ThumbnailData>, documents: Record<string, DocumentData>, password?: string, encryptionLevel?: EncryptionLevel): Promise<DownloadResponse> => {
+/// Checks the pages before anything is written. Problems fail the download like an error of the merge would,
+/// features that don't survive it are only warned about
+const preflightExport = async (thumbnails: unknown[]) => {
+  const report = await invoke('preflight_export', { thumbnails }) as PreflightReport
+  if (report.issues.length > 0) {
+    console.error('Export preflight failed:', report.issues)
+    throw report.issues[0]
+  }
+  report.lost_features.forEach(lost => toast.warning(i18n.t('documents.preflight.lost_features', {
+    file: lost.file_name,
+    features: lost.features.map(feature => i18n.t(`documents.preflight.features.${feature}`)).join(', ')
+  })))
+}
+
+/// References to objects missing from a damaged source are exported empty, which is only warned about
+const warnAboutBrokenReferences = (res: DownloadResponse) => {
+  res.broken_references.forEach(broken => toast.warning(i18n.t('documents.download_all_form.messages.broken_references', {
+    file: broken.file_name,
+    count: broken.count
+  })))
+}
+
+/// Asks where to save an export. The dialog already confirms replacing an existing file, so it is overwritten
+export const chooseSavePath = async (fileName: string): Promise<OutputDestination | null> => {
+  const name = fileName.toLowerCase().endsWith('.pdf') ? fileName : `${fileName}.pdf`
+  const filePath = await save({ defaultPath: name, filters: [{ name: 'PDF', extensions: ['pdf'] }] })
+  if (!filePath) return null
+  return { file_path: filePath, overwrite: 'Overwrite' }
+}
+
+export const handleDownloadDocument = async (document: DocumentData, pagesInDocument: string[], thumbnailsLookup: Record<string, ThumbnailData>, documents: Record<string, DocumentData>, password?: string, encryptionLevel?: EncryptionLevel, options?: ExportOptions, destination?: OutputDestination): Promise<DownloadResponse> => {
   const thumbnails = pagesInDocument.map(thumbnailId => {
     const thumbnail = thumbnailsLookup[thumbnailId]
     if (!thumbnail) return null
@@ -12,21 +45,34 @@ export const handleDownloadDocument = async (document: DocumentData, pagesInDocu
     return {
       file_path: thumbnail.file_path,
       page_index: thumbnail.page_index,
-      password: originalDocument?.password
+      password: originalDocument?.password,
+      crop: thumbnail.crop
     }
   }).filter(Boolean)
+  await preflightExport(thumbnails)
   let res = await invoke('download_file', {
     fileName: document.file_name,
     thumbnails,
     password,
-    encryptionLevel
-  })
-  return res as DownloadResponse
+    encryptionLevel,
+    options,
+    destination
+  }) as DownloadResponse
+  warnAboutBrokenReferences(res)
+  return res
 }
 
-export const handleDownloadAllDocuments = async (fileName: string, pagesInDocuments: Record<string, string[]>, thumbnailsLookup: Record<string, ThumbnailData>, documents: Record<string, DocumentData>, password?: string, encryptionLevel?: EncryptionLevel): Promise<DownloadResponse> => {
+export const handleDownloadAllDocuments = async (fileName: string, pagesInDocuments: Record<string, string[]>, thumbnailsLookup: Record<string, ThumbnailData>, documents: Record<string, DocumentData>, password?: string, encryptionLevel?: EncryptionLevel, options?: ExportOptions, withCover?: boolean, destination?: OutputDestination): Promise<DownloadResponse> => {
+  // A cover page listing the documents in order, before their pages
+  const cover: GeneratedPage[] = withCover
+    ? [{
+      type: 'Separator',
+      text: [fileName, ...Object.keys(pagesInDocuments).map(id => documents[id]?.file_name).filter(Boolean)].join('\n')
+    }]
+    : []
+
   // Flatten all pages in all documents, preserving order
-  const thumbnails = Object.values(pagesInDocuments).flatMap(pagesInDocument =>
+  const pages = Object.values(pagesInDocuments).flatMap(pagesInDocument =>
     pagesInDocument.map(thumbnailId => {
       const thumbnail = thumbnailsLookup[thumbnailId]
       if (!thumbnail) return null
@@ -34,18 +80,23 @@ export const handleDownloadAllDocuments = async (fileName: string, pagesInDocume
       return {
         file_path: thumbnail.file_path,
         page_index: thumbnail.page_index,
-        password: originalDocument?.password
+        password: originalDocument?.password,
+        crop: thumbnail.crop
       }
     })
   ).filter(Boolean)
+  const thumbnails = [...cover, ...pages]
+  await preflightExport(thumbnails)
   let res = await invoke('download_file', {
     fileName,
     thumbnails,
     password,
-    encryptionLevel
-  })
-
-  return res as DownloadResponse
+    encryptionLevel,
+    options,
+    destination
+  }) as DownloadResponse
+  warnAboutBrokenReferences(res)
+  return res
 }
 
 export const sortableDocument = (documentId: string, index: number): ReturnType<typeof useSortable> => {
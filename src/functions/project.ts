@@ -0,0 +1,108 @@
+import { lockedDocument } from '@/components/pages/home/functions'
+import { isPasswordError } from '@/lib/errors'
+import { DocumentData, DownloadResponse, ExportOptions, ProjectData, ProjectOpenResponse, ThumbnailData, ThumbnailReadyEvent, ThumbnailsFinishedEvent } from '@/lib/types/file-upload.types'
+import { invoke } from '@tauri-apps/api/core'
+import { listen } from '@tauri-apps/api/event'
+import { open, save } from '@tauri-apps/plugin-dialog'
+
+const projectFilters = [{ name: 'Ignis Project', extensions: ['ignis'] }]
+
+export const saveProject = async (documents: Record<string, DocumentData>, exportOptions: ExportOptions): Promise<DownloadResponse | null> => {
+  const filePath = await save({ filters: projectFilters })
+  if (!filePath) return null
+
+  const docs = Object.values(documents)
+  // Pages refer to what is rendered, which for text and images is a temporary PDF
+  const sourcePaths = Object.fromEntries(docs.map(doc => [doc.file_path, doc.source_path ?? doc.file_path]))
+  const sourcePath = (filePath: string) => sourcePaths[filePath] ?? filePath
+
+  const project: ProjectData = {
+    // Only whether a password is needed, never the password itself
+    sources: docs.map(doc => ({
+      file_path: sourcePath(doc.file_path),
+      encrypted: !!doc.password || doc.decrypted === false
+    })),
+    documents: docs.map(doc => ({
+      file_name: doc.file_name,
+      file_path: sourcePath(doc.file_path),
+      pages: doc.thumbnails.map(thumbnail => ({
+        file_path: sourcePath(thumbnail.file_path),
+        page_index: thumbnail.page_index,
+        crop: thumbnail.crop
+      }))
+    })),
+    export_options: exportOptions
+  }
+
+  return await invoke('save_project', { filePath, project }) as DownloadResponse
+}
+
+/// Renders all pages of a file, resolving once every thumbnail has arrived
+const renderSource = async (filePath: string): Promise<DocumentData> => {
+  const id = crypto.randomUUID()
+  const thumbnails: ThumbnailData[] = []
+  let onFinished = () => {}
+  const finished = new Promise<void>(resolve => { onFinished = resolve })
+
+  const unlistenReady = await listen<ThumbnailReadyEvent>('thumbnail-ready', event => {
+    if (event.payload.document_id === id) thumbnails.push(event.payload.thumbnail)
+  })
+  const unlistenFinished = await listen<ThumbnailsFinishedEvent>('thumbnails-finished', event => {
+    if (event.payload.document_id === id) onFinished()
+  })
+
+  try {
+    const document = await invoke('generate_thumbnails', { filePath, documentId: id }) as DocumentData
+    await finished
+    return {
+      ...document,
+      thumbnails: thumbnails.sort((a, b) => a.page_index - b.page_index),
+      decrypted: true,
+      password: ''
+    }
+  } catch (error) {
+    // Nothing is rendered for documents that need a password, they are added locked
+    if (isPasswordError(error)) return lockedDocument(id, filePath, error)
+    throw error
+  } finally {
+    unlistenReady()
+    unlistenFinished()
+  }
+}
+
+export type OpenedProject = {
+  documents: Record<string, DocumentData>
+  exportOptions: ExportOptions
+  sources: ProjectOpenResponse['sources']
+}
+
+export const openProject = async (): Promise<OpenedProject | null> => {
+  const filePath = await open({ multiple: false, directory: false, filters: projectFilters })
+  if (!filePath) return null
+
+  const { project, sources } = await invoke('open_project', { filePath }) as ProjectOpenResponse
+
+  const available = sources.filter(source => source.state !== 'Missing').map(source => source.file_path)
+  const rendered: Record<string, DocumentData> = Object.fromEntries(
+    await Promise.all(available.map(async path => [path, await renderSource(path)] as const))
+  )
+
+  const documents: Record<string, DocumentData> = {}
+  for (const projectDocument of project.documents) {
+    const source = rendered[projectDocument.file_path]
+    if (!source) continue
+
+    const id = crypto.randomUUID()
+    // Locked documents come back without pages until their password is entered again
+    const thumbnails = source.decrypted
+      ? projectDocument.pages.flatMap(page => {
+        const thumbnail = rendered[page.file_path]?.thumbnails.find(t => t.page_index === page.page_index)
+        return thumbnail ? [{ ...thumbnail, id: `${id}_${crypto.randomUUID()}`, crop: page.crop }] : []
+      })
+      : []
+
+    documents[id] = { ...source, id, file_name: projectDocument.file_name, thumbnails }
+  }
+
+  return { documents, exportOptions: project.export_options, sources }
+}
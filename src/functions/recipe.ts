@@ -0,0 +1,27 @@
+import { DownloadResponse, MergeRecipe, RecipeListResponse } from '@/lib/types/file-upload.types'
+import { invoke } from '@tauri-apps/api/core'
+
+export const listRecipes = async (): Promise<RecipeListResponse> => {
+  return await invoke('list_recipes') as RecipeListResponse
+}
+
+export const saveRecipe = async (recipe: MergeRecipe): Promise<MergeRecipe> => {
+  return await invoke('save_recipe', { recipe }) as MergeRecipe
+}
+
+export const deleteRecipe = async (recipeId: string): Promise<boolean> => {
+  return await invoke('delete_recipe', { recipeId }) as boolean
+}
+
+// The password is only needed for recipes that encrypt their output
+export const runRecipe = async (recipeId: string, password?: string): Promise<DownloadResponse> => {
+  return await invoke('run_recipe', { recipeId, password: password || undefined }) as DownloadResponse
+}
+
+export const watchRecipe = async (recipeId: string, password?: string): Promise<void> => {
+  await invoke('watch_recipe', { recipeId, password: password || undefined })
+}
+
+export const unwatchRecipe = async (recipeId: string): Promise<boolean> => {
+  return await invoke('unwatch_recipe', { recipeId }) as boolean
+}
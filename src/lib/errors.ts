@@ -0,0 +1,20 @@
+import { CommandError } from '@/lib/types/file-upload.types'
+import { TFunction } from 'i18next'
+
+export const isCommandError = (error: unknown): error is CommandError =>
+  typeof error === 'object' && error !== null && 'code' in error
+
+export const isPasswordError = (error: unknown): error is CommandError =>
+  isCommandError(error) && (error.code === 'PDF_PASSWORD_REQUIRED' || error.code === 'PDF_PASSWORD_INCORRECT')
+
+/// The message for an error returned by a command, naming the file and page it is about
+export const errorMessage = (t: TFunction, error: unknown): string => {
+  if (!isCommandError(error)) return t('errors.UNKNOWN')
+
+  const message = t(`errors.${error.code}`, { defaultValue: t('errors.UNKNOWN') })
+  if (error.page_index !== undefined && error.page_index !== null) {
+    return t('errors.on_page', { message, file: error.file_name ?? '', page: error.page_index + 1 })
+  }
+  if (error.file_name) return t('errors.on_file', { message, file: error.file_name })
+  return message
+}
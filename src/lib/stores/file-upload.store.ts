@@ -21,6 +21,8 @@ export const useDownloadAllStore = create<DownloadAllStore>(set => ({
   password: '',
   isFormOpen: false,
   isDownloading: false,
+  exportOptions: {},
+  setExportOptions: (exportOptions) => set({ exportOptions }),
   setFileName: (fileName: string) => set({ fileName }),
   setPassword: (password: string) => set({ password }),
   setIsFormOpen: (isFormOpen: boolean) => set({ isFormOpen }),
@@ -33,6 +35,16 @@ export const usePdfPreviewsStore = create<DocumentsState>(set => ({
   addDocument: (document) => set((state) => ({
     documents: { ...state.documents, [document.id]: document },
   })),
+  addThumbnail: (documentId, thumbnail) => set((state) => {
+    if (!state.documents[documentId]) return state
+    // Pages are encoded in parallel and may arrive out of order
+    const thumbnails = [...state.documents[documentId].thumbnails]
+    const index = thumbnails.findIndex(t => t.page_index > thumbnail.page_index)
+    thumbnails.splice(index === -1 ? thumbnails.length : index, 0, thumbnail)
+    return {
+      documents: { ...state.documents, [documentId]: { ...state.documents[documentId], thumbnails } }
+    }
+  }),
   removeDocument: (documentId) => set((state) => {
     console.log('Removing document', documentId)
     const newDocuments = {...state.documents}
@@ -1,5 +1,6 @@
 import { create } from 'zustand'
-import { ThemeState } from '../types/settings.types'
+import { invoke } from '@tauri-apps/api/core'
+import { RenderSettings, RenderSettingsState, ThemeState } from '../types/settings.types'
 
 export const useThemeStore = create<ThemeState>(set => ({
   isDarkMode: JSON.parse(localStorage.getItem('isDarkMode') ?? 'false'),
@@ -21,4 +22,19 @@ export const useThemeStore = create<ThemeState>(set => ({
         isDarkMode: !state.isDarkMode,
       }
     }),
-}))
\ No newline at end of file
+}))
+
+// Mirrors the defaults of the backend
+const defaultRenderSettings: RenderSettings = {
+  thumbnails: { format: 'WebpLossy', quality: 85 },
+  full_res: { format: 'WebpLossless', quality: 100 },
+}
+
+export const useRenderSettingsStore = create<RenderSettingsState>(set => ({
+  renderSettings: JSON.parse(localStorage.getItem('renderSettings') ?? 'null') ?? defaultRenderSettings,
+  setRenderSettings: (renderSettings: RenderSettings) => {
+    localStorage.setItem('renderSettings', JSON.stringify(renderSettings))
+    invoke('set_render_settings', { renderSettings }).catch(console.error)
+    set({ renderSettings })
+  },
+}))
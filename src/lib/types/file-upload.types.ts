@@ -18,6 +18,8 @@ export type DownloadAllStore = {
   password?: string
   isFormOpen: boolean
   isDownloading: boolean
+  exportOptions: ExportOptions
+  setExportOptions: (exportOptions: ExportOptions) => void
   setFileName: (fileName: string) => void
   setPassword: (password: string) => void
   setIsFormOpen: (isFormOpen: boolean) => void
@@ -31,22 +33,30 @@ export type ThumbnailData = {
   thumbnail: string
   width: number
   height: number
+  crop?: RegionRect // points on the page as displayed, applied on export
 }
 
 export type DocumentData = {
   id: string,
   file_name: string,
-  file_path: string,
+  file_path: string, // what is rendered, a temporary PDF for converted files
+  source_path?: string, // the file that was opened
   thumbnails: ThumbnailData[]
-  error?: string
+  page_count: number
+  repair?: RepairReport // set if the file was damaged and is shown repaired
   password?: string
   decrypted?: boolean
 }
 
+export type RepairReport = {
+  objects_lost: number
+}
+
 export type DocumentsState = {
   documents: Record<string, DocumentData>
   setDocuments: (docuemnts: Record<string, DocumentData>) => void
   addDocument: (document: DocumentData) => void
+  addThumbnail: (documentId: string, thumbnail: ThumbnailData) => void
   removeDocument: (documentId: string) => void
   removeThumbnail: (documentId: string, thumbnailId: string) => void
   duplicateThumbnail: (documentId: string, thumbnailId: string) => void
@@ -54,8 +64,38 @@ export type DocumentsState = {
   updateFileName: (documentId: string, fileName: string) => void
 }
 
+export type ThumbnailReadyEvent = {
+  document_id: string
+  thumbnail: ThumbnailData
+  rendered: number
+  total: number
+}
+
+export type ThumbnailsFinishedEvent = {
+  document_id: string
+  cancelled: boolean
+  error?: CommandError
+}
+
 export type FullImageData = {
-  data_url: string,
+  url: string,
+  width: number,
+  height: number,
+  page_width: number, // points
+  page_height: number
+}
+
+export type RegionRect = {
+  x: number,
+  y: number,
+  width: number,
+  height: number
+}
+
+export type RegionImageData = {
+  url: string,
+  region: RegionRect, // points, clamped to the page
+  scale: number, // pixels per point
   width: number,
   height: number
 }
@@ -64,6 +104,126 @@ export type DownloadResponse = {
   key: string,
   file_name: string,
   file_path: string
+  broken_references: { // sources exported with missing objects as null
+    file_name: string
+    file_path: string
+    count: number
+  }[]
 }
 
-export type EncryptionLevel = 'Aes128' | 'Aes256'
\ No newline at end of file
+export type EncryptionLevel = 'Aes128' | 'Aes256'
+
+export type PageBox = 'Media' | 'Crop' | 'Bleed' | 'Trim' | 'Art'
+
+export type PaperSize = 'A3' | 'A4' | 'A5' | 'Letter' | 'Legal' | 'Tabloid'
+
+export type PageLayout =
+  | { type: 'NUp', columns: number, rows: number, gap?: number, border?: number }
+  | { type: 'Booklet' }
+
+export type ExportOptions = {
+  trim_to?: PageBox
+  page_size?: PaperSize
+  fixed_orientation?: boolean
+  layout?: PageLayout
+  pad_to_even?: boolean
+  table_of_contents?: TableOfContents
+}
+
+export type TableOfContents = {
+  title: string
+  outlines?: boolean
+}
+
+export type OverwritePolicy = 'Unique' | 'Overwrite' | 'Fail'
+
+/// Without a directory or file path, exports go to the Downloads folder
+export type OutputDestination = {
+  directory?: string
+  file_path?: string // chosen in the save dialog
+  overwrite?: OverwritePolicy
+}
+
+export type GeneratedPage =
+  | { type: 'Blank', size?: PaperSize }
+  | { type: 'Separator', text: string, size?: PaperSize }
+
+export type ProjectPage = {
+  file_path: string
+  page_index: number
+  crop?: RegionRect
+}
+
+export type ProjectDocument = {
+  file_name: string
+  file_path: string
+  pages: ProjectPage[]
+}
+
+export type ProjectSource = {
+  file_path: string
+  file_hash?: string
+  encrypted: boolean
+}
+
+export type ProjectData = {
+  version?: number
+  sources: ProjectSource[]
+  documents: ProjectDocument[]
+  export_options: ExportOptions
+}
+
+export type SourceState = 'Ok' | 'Missing' | 'Modified'
+
+export type ProjectOpenResponse = {
+  project: ProjectData
+  sources: { file_path: string, state: SourceState }[]
+}
+
+export type InputOrder = 'Name' | 'Modified'
+
+export type RecipeInput = {
+  pattern: string // a file, or a file name pattern with * and ? such as scans/*.jpg
+  order?: InputOrder
+}
+
+export type MergeRecipe = {
+  id: string // empty until saved
+  name: string
+  file_name: string
+  folder: string
+  inputs: RecipeInput[]
+  encryption_level?: EncryptionLevel
+  export_options: ExportOptions
+}
+
+export type RecipeListResponse = {
+  recipes: MergeRecipe[]
+  watched: string[]
+}
+
+export type CommandError = {
+  code: string // e.g. PDF_LOAD_ERROR, also the key of its message under `errors`
+  file_name?: string
+  file_path?: string
+  page_index?: number
+  cause?: string // the underlying error, for logs
+}
+
+export type RecipeRunEvent = {
+  recipe_id: string
+  result?: DownloadResponse
+  error?: CommandError
+}
+
+export type PdfFeature = 'Forms' | 'JavaScript' | 'Signatures' | 'Attachments' | 'Links'
+
+export type PreflightReport = {
+  issues: CommandError[] // every problem the export would fail on, empty if it can run
+  lost_features: {
+    file_name: string
+    file_path: string
+    features: PdfFeature[]
+  }[]
+  estimated_size: number // bytes
+}
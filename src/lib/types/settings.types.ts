@@ -1,4 +1,21 @@
 export type ThemeState = {
   isDarkMode: boolean
   toggleDarkMode: (isDarkMode?: boolean) => void
-}
\ No newline at end of file
+}
+
+export type OutputFormat = 'Png' | 'WebpLossless' | 'WebpLossy' | 'Jpeg' | 'Avif'
+
+export type ImageEncoding = {
+  format: OutputFormat
+  quality: number // 1-100, ignored by the lossless formats
+}
+
+export type RenderSettings = {
+  thumbnails: ImageEncoding
+  full_res: ImageEncoding
+}
+
+export type RenderSettingsState = {
+  renderSettings: RenderSettings
+  setRenderSettings: (renderSettings: RenderSettings) => void
+}